@@ -0,0 +1,144 @@
+//!
+//! Interpreter throughput on a few representative workloads, reported in instructions
+//! per second, both for a single machine and summed over a `Batch` running on all cores,
+//! and the cost of turning a frame into pixels. Runs the core only, without SDL or audio:
+//!
+//! ```text
+//! cargo bench --bench interpreter
+//! ```
+//!
+use std::{hint::black_box, io};
+
+use chip8_core::{
+    batch::Batch,
+    palette::Palette,
+    quirks::Platform,
+    recorder::{FrameRecorder, RawFrameWriter},
+    screenshot, Chip8,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// Instructions executed per benchmark iteration
+const INSTRUCTIONS: u64 = 10_000;
+
+/// Enough machines to keep every core of a typical machine busy
+const BATCH_SIZES: [usize; 2] = [1, 64];
+
+/// Register arithmetic and flags in a tight loop
+const ARITHMETIC: &[u8] = &[
+    0x60, 0x01, // 200: V0 = 1
+    0x71, 0x01, // 202: V1 += 1
+    0x82, 0x14, // 204: V2 += V1
+    0x83, 0x25, // 206: V3 -= V2
+    0x84, 0x36, // 208: V4 = V3 >> 1
+    0x85, 0x4E, // 20A: V5 = V4 << 1
+    0x86, 0x02, // 20C: V6 &= V0
+    0x12, 0x02, // 20E: jump 202
+];
+
+/// Tall sprites drawn all over the screen, with wrapping and clipping
+const SPRITES: &[u8] = &[
+    0xF0, 0x29, // 200: I = font digit V0
+    0xD1, 0x2F, // 202: draw 15 rows at V1, V2
+    0x71, 0x03, // 204: V1 += 3
+    0x72, 0x05, // 206: V2 += 5
+    0x70, 0x01, // 208: V0 += 1
+    0x12, 0x00, // 20A: jump 200
+];
+
+/// BCD conversion and register stores and loads
+const BCD_MEMORY: &[u8] = &[
+    0xA3, 0x00, // 200: I = 300
+    0xF3, 0x33, // 202: store the BCD of V3 at I
+    0xF2, 0x65, // 204: load V0 to V2 from I
+    0xF7, 0x55, // 206: store V0 to V7 at I
+    0xF3, 0x1E, // 208: I += V3
+    0x73, 0x01, // 20A: V3 += 1
+    0x12, 0x00, // 20C: jump 200
+];
+
+fn machine(rom: &[u8]) -> Chip8 {
+    let mut chip = Chip8::new(None);
+    chip.set_quirks(Platform::CosmacVip.quirks());
+    chip.seed_rng(0);
+    chip.load_rom_bytes(rom);
+    chip
+}
+
+fn interpreter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpreter");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+
+    for (name, rom) in [
+        ("arithmetic", ARITHMETIC),
+        ("sprites", SPRITES),
+        ("bcd_memory", BCD_MEMORY),
+    ] {
+        let mut chip = machine(rom);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                chip.run_instructions(INSTRUCTIONS);
+                black_box(&chip);
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch");
+
+    for (name, rom) in [
+        ("arithmetic", ARITHMETIC),
+        ("sprites", SPRITES),
+        ("bcd_memory", BCD_MEMORY),
+    ] {
+        for count in BATCH_SIZES {
+            // Instructions of all machines together, divide by the number of cores for
+            // the rate of one core
+            group.throughput(Throughput::Elements(INSTRUCTIONS * count as u64));
+
+            let mut batch = Batch::new(rom, count, Platform::CosmacVip.quirks(), 0);
+            group.bench_with_input(BenchmarkId::new(name, count), &count, |b, _| {
+                b.iter(|| {
+                    batch.step(INSTRUCTIONS as u32);
+                    black_box(&batch);
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+fn render(c: &mut Criterion) {
+    // A screen full of sprites, so there is something to convert
+    let mut chip = machine(SPRITES);
+    chip.run_instructions(INSTRUCTIONS);
+
+    let mut group = c.benchmark_group("render");
+    group.throughput(Throughput::Elements(1));
+
+    for scale in [1, 10] {
+        group.bench_with_input(
+            BenchmarkId::new("indexed_pixels", scale),
+            &scale,
+            |b, &scale| b.iter(|| screenshot::indexed_pixels(black_box(&chip), scale)),
+        );
+    }
+
+    let mut writer = RawFrameWriter::new(io::sink(), 10, &Palette::default());
+    group.bench_function("raw_rgb", |b| {
+        b.iter(|| writer.record(black_box(&chip)).unwrap())
+    });
+
+    let mut batch = Batch::new(SPRITES, 1, Platform::CosmacVip.quirks(), 0);
+    batch.step(INSTRUCTIONS as u32);
+    group.bench_function("packed", |b| b.iter(|| black_box(batch.framebuffers())));
+
+    group.finish();
+}
+
+criterion_group!(benches, interpreter, batch, render);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+//!
+//! Parses arbitrary save states and runs the machine from the ones that load.
+//!
+#![no_main]
+
+use chip8_core::{state::SaveState, Chip8};
+use libfuzzer_sys::fuzz_target;
+
+const TICKS: u32 = 1000;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(state) = SaveState::from_bytes(data) else {
+        return;
+    };
+    assert_eq!(SaveState::from_bytes(&state.to_bytes()), Ok(state.clone()));
+
+    let mut chip = Chip8::new(None);
+    if chip.load_state(&state).is_err() {
+        return;
+    }
+
+    for tick in 0..TICKS {
+        chip.tick();
+        if tick % 10 == 0 {
+            chip.tick_timers();
+        }
+    }
+});
@@ -0,0 +1,40 @@
+//!
+//! Runs arbitrary ROMs for a bounded number of frames. The first byte selects the
+//! platform, random source and timing model, the second the buttons held down, and
+//! the rest is the ROM image.
+//!
+#![no_main]
+
+use chip8_core::{
+    quirks::Platform, rng::RandomSource, scheduler::Scheduler, timing::TimingModel, Chip8,
+};
+use libfuzzer_sys::fuzz_target;
+
+const FRAMES: u32 = 60;
+const INSTRUCTIONS_PER_SECOND: u32 = 700;
+
+fuzz_target!(|data: &[u8]| {
+    let [config, keys, rom @ ..] = data else {
+        return;
+    };
+
+    let platform = Platform::ALL[*config as usize % Platform::ALL.len()];
+    let random = RandomSource::ALL[(*config as usize >> 2) % RandomSource::ALL.len()];
+    let timing = TimingModel::ALL[(*config as usize >> 3) % TimingModel::ALL.len()];
+
+    let mut chip = Chip8::new(None);
+    chip.set_quirks(platform.quirks());
+    chip.set_random_source(random);
+    chip.seed_rng(0);
+    chip.load_rom_bytes(rom);
+
+    let mut scheduler = Scheduler::new(INSTRUCTIONS_PER_SECOND);
+    scheduler.set_timing(timing);
+
+    for frame in 0..FRAMES {
+        // Hold each button for a few frames so ROMs waiting on FX0A get past it
+        let button = (frame / 4) as usize % 8;
+        chip.key_press(button, keys & (1 << button) != 0);
+        scheduler.run_frame(&mut chip);
+    }
+});
@@ -0,0 +1,205 @@
+//!
+//! Python bindings for the emulator core, built as the `chip8` extension module with maturin.
+//!
+//! ```python
+//! import chip8
+//! import numpy as np
+//!
+//! machine = chip8.Chip8(seed=1234)
+//! machine.load_rom(open("brix.ch8", "rb").read())
+//! for _ in range(60):
+//!     machine.step(10)
+//!     machine.tick_timers()
+//! screen = np.frombuffer(machine.screen(), dtype=np.uint8).reshape(chip8.HEIGHT, chip8.WIDTH)
+//! ```
+//!
+use chip8_core::{
+    quirks::Platform, rng::RandomSource, state::SaveState, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyBytes};
+
+///
+/// A CHIP-8 machine without audio output
+///
+#[pyclass(name = "Chip8", unsendable)]
+struct PyChip8 {
+    chip: chip8_core::Chip8,
+}
+
+#[pymethods]
+impl PyChip8 {
+    /// Platform and random source default to the ones of the emulator frontend
+    #[new]
+    #[pyo3(signature = (platform = None, seed = None, random = None))]
+    fn new(platform: Option<&str>, seed: Option<u64>, random: Option<&str>) -> PyResult<Self> {
+        let platform: Platform = platform
+            .map_or(Ok(Platform::default()), str::parse)
+            .map_err(PyValueError::new_err)?;
+        let random: RandomSource = random
+            .map_or(Ok(RandomSource::default()), str::parse)
+            .map_err(PyValueError::new_err)?;
+
+        let mut chip = chip8_core::Chip8::new(None);
+        chip.set_quirks(platform.quirks());
+        chip.set_random_source(random);
+        if let Some(seed) = seed {
+            chip.seed_rng(seed);
+        }
+
+        Ok(PyChip8 { chip })
+    }
+
+    /// Loads a ROM image at the start address
+    fn load_rom(&mut self, rom: &[u8]) -> PyResult<()> {
+        let available = self.chip.memory().len() - self.chip.start_address() as usize;
+        if rom.len() > available {
+            return Err(PyValueError::new_err(format!(
+                "ROM is {} bytes, at most {} fit in memory",
+                rom.len(),
+                available
+            )));
+        }
+
+        self.chip.load_rom_bytes(rom);
+        Ok(())
+    }
+
+    /// Executes the given number of instructions
+    #[pyo3(signature = (cycles = 1))]
+    fn step(&mut self, cycles: u32) {
+        self.chip.run_instructions(cycles as u64);
+    }
+
+    /// Counts the delay and sound timers down, once per 60 Hz frame
+    fn tick_timers(&mut self) {
+        self.chip.tick_timers();
+    }
+
+    /// Restarts the loaded ROM, keeping memory outside the ROM image
+    fn reset(&mut self) {
+        self.chip.soft_reset();
+    }
+
+    /// Power-cycles the machine, clearing all of memory before reloading the ROM
+    fn hard_reset(&mut self) {
+        self.chip.hard_reset();
+    }
+
+    fn set_key(&mut self, button: usize, pressed: bool) -> PyResult<()> {
+        if button >= 16 {
+            return Err(PyValueError::new_err("button must be between 0 and 15"));
+        }
+
+        self.chip.key_press(button, pressed);
+        Ok(())
+    }
+
+    /// The screen as WIDTH * HEIGHT bytes, 1 for lit pixels, row by row
+    fn screen<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        let pixels: Vec<u8> = self
+            .chip
+            .display()
+            .pixels()
+            .map(|pixel| pixel as u8)
+            .collect();
+        PyBytes::new(py, &pixels)
+    }
+
+    #[pyo3(signature = (address, length = 1))]
+    fn read_ram<'py>(
+        &self,
+        py: Python<'py>,
+        address: usize,
+        length: usize,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = address
+            .checked_add(length)
+            .and_then(|end| self.chip.memory().get(address..end))
+            .ok_or_else(|| PyValueError::new_err("read goes past the end of RAM"))?;
+        Ok(PyBytes::new(py, bytes))
+    }
+
+    fn write_ram(&mut self, address: u16, data: &[u8]) -> PyResult<()> {
+        self.chip
+            .write_memory(address, data)
+            .map_err(PyValueError::new_err)
+    }
+
+    fn get_v(&self, x: usize) -> PyResult<u8> {
+        self.chip
+            .v_registers()
+            .get(x)
+            .copied()
+            .ok_or_else(|| PyValueError::new_err("register must be between 0 and 15"))
+    }
+
+    fn set_v(&mut self, x: usize, value: u8) -> PyResult<()> {
+        self.chip
+            .set_v_register(x, value)
+            .map_err(PyValueError::new_err)
+    }
+
+    #[getter]
+    fn get_i(&self) -> u16 {
+        self.chip.i_register()
+    }
+
+    #[setter]
+    fn set_i(&mut self, value: u16) {
+        self.chip.set_i_register(value);
+    }
+
+    #[getter]
+    fn get_pc(&self) -> u16 {
+        self.chip.program_counter()
+    }
+
+    #[setter]
+    fn set_pc(&mut self, value: u16) {
+        self.chip.set_program_counter(value);
+    }
+
+    #[getter]
+    fn get_delay_timer(&self) -> u8 {
+        self.chip.delay_timer()
+    }
+
+    #[setter]
+    fn set_delay_timer(&mut self, value: u8) {
+        self.chip.set_delay_timer(value);
+    }
+
+    #[getter]
+    fn get_sound_timer(&self) -> u8 {
+        self.chip.sound_timer()
+    }
+
+    #[setter]
+    fn set_sound_timer(&mut self, value: u8) {
+        self.chip.set_sound_timer(value);
+    }
+
+    /// Number of frames run so far
+    #[getter]
+    fn frame_count(&self) -> u64 {
+        self.chip.frame_count()
+    }
+
+    /// The complete machine state, including the random number generator, as bytes
+    fn save_state<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.chip.save_state().to_bytes())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        let state = SaveState::from_bytes(state).map_err(PyValueError::new_err)?;
+        self.chip.load_state(&state).map_err(PyValueError::new_err)
+    }
+}
+
+#[pymodule]
+fn chip8(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyChip8>()?;
+    module.add("WIDTH", SCREEN_WIDTH)?;
+    module.add("HEIGHT", SCREEN_HEIGHT)?;
+    Ok(())
+}
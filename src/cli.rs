@@ -0,0 +1,151 @@
+use chip8_core::{
+    font::FontSet,
+    palette::Palette,
+    quirks::Platform,
+    rng::RandomSource,
+    scheduler::{DEFAULT_INSTRUCTIONS_PER_SECOND, MAX_SPEED},
+    timing::TimingModel,
+};
+use clap::Parser;
+use std::path::PathBuf;
+
+///
+/// Command line options of the emulator frontend
+///
+#[derive(Parser, Debug)]
+#[command(name = "chip8_emulator", version, about = "CHIP-8 emulator")]
+pub struct Args {
+    /// Path to the ROM to run
+    pub rom_path: String,
+
+    /// Size of a single CHIP-8 pixel in screen pixels
+    #[arg(short, long, default_value_t = 23, value_parser = clap::value_parser!(u32).range(1..=64))]
+    pub scale: u32,
+
+    /// Number of instructions executed per second
+    #[arg(long = "ips", default_value_t = DEFAULT_INSTRUCTIONS_PER_SECOND, value_name = "COUNT")]
+    pub instructions_per_second: u32,
+
+    /// Instruction timing: fixed (every instruction takes the same time) or cosmac-vip (cycle accurate)
+    #[arg(short, long, default_value_t = TimingModel::default())]
+    pub timing: TimingModel,
+
+    /// Speed multiplier while the turbo key (Tab) is held
+    #[arg(long, default_value_t = 4.0, value_name = "FACTOR", value_parser = parse_speed)]
+    pub turbo_speed: f64,
+
+    /// Speed multiplier in slow-motion mode (toggled with M)
+    #[arg(long, default_value_t = 0.25, value_name = "FACTOR", value_parser = parse_speed)]
+    pub slow_speed: f64,
+
+    /// Quirk preset to emulate: legacy, cosmac-vip, chip-48 or super-chip
+    #[arg(short, long, default_value_t = Platform::default())]
+    pub platform: Platform,
+
+    /// Address the ROM is loaded at and started from, in hex (600 for ETI-660 programs)
+    #[arg(long, default_value = "200", value_name = "ADDRESS", value_parser = parse_address)]
+    pub start_address: u16,
+
+    /// Font drawn by FX29 and FX30: cosmac-vip, dream-6800, eti-660 or super-chip
+    #[arg(long, default_value_t = FontSet::default(), conflicts_with = "font_file")]
+    pub font: FontSet,
+
+    /// Font binary to load instead of a built-in font: 80 bytes of small glyphs, optionally
+    /// followed by 100 or 160 bytes of large glyphs. Can't be used with movies
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["record_movie", "play_movie"])]
+    pub font_file: Option<String>,
+
+    /// Address the font is loaded at, in hex
+    #[arg(long, default_value = "0", value_name = "ADDRESS", value_parser = parse_address)]
+    pub font_address: u16,
+
+    /// Display colours: classic, mono, amber, lcd or BACKGROUND:FOREGROUND hex colours
+    #[arg(long, default_value_t = Palette::default())]
+    pub palette: Palette,
+
+    /// Start in fullscreen mode
+    #[arg(short, long)]
+    pub fullscreen: bool,
+
+    /// Disable sound
+    #[arg(short, long)]
+    pub mute: bool,
+
+    /// Start with the emulator paused
+    #[arg(long)]
+    pub paused: bool,
+
+    /// File overriding the default key bindings
+    #[arg(short, long, value_name = "FILE")]
+    pub keymap: Option<String>,
+
+    /// Directory screenshots and recordings started with hotkeys are saved to
+    #[arg(long, default_value = ".", value_name = "DIR")]
+    pub capture_dir: PathBuf,
+
+    /// Record the session as an animated GIF
+    #[arg(long, value_name = "FILE")]
+    pub record_gif: Option<PathBuf>,
+
+    /// Record raw RGB24 frames at 60 fps, use - for stdout
+    #[arg(long, value_name = "FILE")]
+    pub record_raw: Option<PathBuf>,
+
+    /// Record the beeper output as a WAV file
+    #[arg(long, value_name = "FILE")]
+    pub record_wav: Option<PathBuf>,
+
+    /// Size of a CHIP-8 pixel in recordings
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=64))]
+    pub record_scale: u32,
+
+    /// Seed for the random number generator, random if omitted
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Random number algorithm used by CXNN: splitmix64 or cosmac-vip (the original interpreter's routine)
+    #[arg(long, default_value_t = RandomSource::default())]
+    pub random: RandomSource,
+
+    /// Record all inputs to a movie file
+    #[arg(long, value_name = "FILE", conflicts_with = "play_movie")]
+    pub record_movie: Option<PathBuf>,
+
+    /// Replay the inputs of a movie file, using the movie's seed and emulation settings
+    #[arg(long, value_name = "FILE")]
+    pub play_movie: Option<PathBuf>,
+
+    /// Cheat file to load, defaults to the ROM's path with a .cht extension.
+    /// No cheats are loaded while a movie is recorded or played
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["record_movie", "play_movie"])]
+    pub cheats: Option<PathBuf>,
+
+    /// Read RAM search and cheat commands from the terminal while the window is open
+    #[arg(long)]
+    pub console: bool,
+
+    /// Rhai script run alongside the game, see the README for the functions available to it
+    #[arg(long, value_name = "FILE")]
+    pub script: Option<PathBuf>,
+
+    /// Run without a window or sound and print the final screen to stdout.
+    /// Requires --frames unless a movie is played
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Quit after running the given number of frames
+    #[arg(long, value_name = "COUNT")]
+    pub frames: Option<u64>,
+}
+
+fn parse_address(s: &str) -> Result<u16, String> {
+    let hex = s.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(hex, 16).map_err(|_| format!("'{}' is not a hex address", s))
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    s.parse()
+        .ok()
+        .filter(|speed| (0.0..=MAX_SPEED).contains(speed))
+        .ok_or_else(|| format!("'{}' is not a speed between 0 and {}", s, MAX_SPEED))
+}
@@ -0,0 +1,141 @@
+use std::{
+    io::{self, BufRead},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use chip8_core::{
+    cheats::{Cheat, Cheats, RamSearch, SearchFilter},
+    Chip8,
+};
+
+const HELP: &str = "\
+Commands:
+  search                           start a new RAM search with every address as a candidate
+  eq <hex>                         keep candidates whose value equals <hex>
+  changed | unchanged | inc | dec  keep candidates whose value changed in that way since the last filter
+  list                             print the remaining candidates and their values
+  cheat <name> <address> <bytes>   write the bytes (hex) to the address every frame
+  remove <name>                    remove a cheat
+  cheats                           print the active cheats
+  save                             save the active cheats to the ROM's cheat file";
+
+/// Candidates printed by `list`, longer lists are cut off
+const MAX_LISTED: usize = 32;
+
+///
+/// Commands typed into the terminal while the window is open, used to search RAM
+/// and manage cheats
+///
+pub struct Console {
+    lines: Receiver<String>,
+    search: Option<RamSearch>,
+    cheats_path: PathBuf,
+}
+
+impl Console {
+    pub fn spawn(cheats_path: PathBuf) -> Self {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        eprintln!("Console ready, type 'help' for a list of commands");
+        Console {
+            lines,
+            search: None,
+            cheats_path,
+        }
+    }
+
+    ///
+    /// Runs the commands typed since the last call
+    ///
+    pub fn poll(&mut self, chip: &mut Chip8, cheats: &mut Cheats) {
+        while let Ok(line) = self.lines.try_recv() {
+            if let Err(error) = self.run(&line, chip, cheats) {
+                eprintln!("{}", error);
+            }
+        }
+    }
+
+    fn run(&mut self, line: &str, chip: &mut Chip8, cheats: &mut Cheats) -> Result<(), String> {
+        let words: Vec<_> = line.split_whitespace().collect();
+        let filter = match words[..] {
+            [] => return Ok(()),
+            ["eq", value] => Some(SearchFilter::Equal(
+                u8::from_str_radix(value, 16).map_err(|_| "value must be a hex byte")?,
+            )),
+            ["changed"] => Some(SearchFilter::Changed),
+            ["unchanged"] => Some(SearchFilter::Unchanged),
+            ["inc"] => Some(SearchFilter::Increased),
+            ["dec"] => Some(SearchFilter::Decreased),
+            _ => None,
+        };
+
+        if let Some(filter) = filter {
+            let search = self
+                .search
+                .as_mut()
+                .ok_or("no search running, start one with 'search'")?;
+            eprintln!("{} candidates left", search.filter(chip, filter));
+            return Ok(());
+        }
+
+        match words[..] {
+            ["help"] => eprintln!("{}", HELP),
+            ["search"] => {
+                let search = RamSearch::new(chip);
+                eprintln!("{} candidates", search.candidates().len());
+                self.search = Some(search);
+            }
+            ["list"] => {
+                let search = self
+                    .search
+                    .as_ref()
+                    .ok_or("no search running, start one with 'search'")?;
+                for &address in search.candidates().iter().take(MAX_LISTED) {
+                    eprintln!("{:03X}: {:02X}", address, search.value(address));
+                }
+                if search.candidates().len() > MAX_LISTED {
+                    eprintln!("... {} more", search.candidates().len() - MAX_LISTED);
+                }
+            }
+            ["cheat", name, address, ref bytes @ ..] => {
+                let cheat: Cheat =
+                    format!("{} = {}: {}", name, address, bytes.join(" ")).parse()?;
+                cheat.apply(chip)?;
+                cheats.add(cheat);
+            }
+            ["remove", name] => {
+                cheats
+                    .remove(name)
+                    .ok_or_else(|| format!("no cheat named '{}'", name))?;
+            }
+            ["cheats"] => {
+                for cheat in cheats.iter() {
+                    eprintln!("{}", cheat);
+                }
+            }
+            ["save"] => {
+                cheats
+                    .save(&self.cheats_path)
+                    .map_err(|error| format!("failed to save cheats: {}", error))?;
+                eprintln!("Saved cheats to {}", self.cheats_path.display());
+            }
+            _ => {
+                return Err(format!(
+                    "unknown command '{}', type 'help' for a list",
+                    line.trim()
+                ))
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,10 +1,26 @@
+#[cfg(feature = "audio")]
 use rodio::{source::SineWave, OutputStream, Sink, Source};
+#[cfg(feature = "audio")]
 use std::time::Duration;
 
+///
+/// Audio backend sounding the beeper. The machine plays it while the sound timer runs
+/// and pauses it when the timer reaches zero
+///
+pub trait Beeper: Send + Sync {
+    fn play(&self);
+    fn pause(&self);
+}
+
+///
+/// Beeper playing a sine tone on the default output device, needs the `audio` feature
+///
+#[cfg(feature = "audio")]
 pub struct AudioBeep {
     sink: Sink,
 }
 
+#[cfg(feature = "audio")]
 impl AudioBeep {
     pub fn new() -> Self {
         let (_stream, stream_handle) = OutputStream::try_default().unwrap();
@@ -19,12 +35,22 @@ impl AudioBeep {
 
         AudioBeep { sink }
     }
+}
 
-    pub fn play(&self) {
+#[cfg(feature = "audio")]
+impl Beeper for AudioBeep {
+    fn play(&self) {
         self.sink.play();
     }
 
-    pub fn pause(&self) {
+    fn pause(&self) {
         self.sink.pause();
     }
 }
+
+#[cfg(feature = "audio")]
+impl Default for AudioBeep {
+    fn default() -> Self {
+        Self::new()
+    }
+}
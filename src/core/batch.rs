@@ -0,0 +1,116 @@
+//!
+//! Many machines running the same ROM in lockstep, stepped in parallel across threads.
+//! Meant for workloads like reinforcement learning and fuzzing that need as many
+//! instructions per second as possible and never open an audio device.
+//!
+use rayon::prelude::*;
+
+use crate::{display, quirks::Quirks, Chip8};
+
+///
+/// Size of a single packed framebuffer: one bit per pixel, rows of 8 bytes with
+/// the leftmost pixel in the most significant bit
+///
+pub const PACKED_FRAME_SIZE: usize = display::PACKED_SIZE;
+
+pub struct Batch {
+    machines: Vec<Chip8>,
+}
+
+impl Batch {
+    ///
+    /// Creates `count` silent machines with the ROM loaded. Machine n uses the RNG seed
+    /// `seed + n`, so every machine of a batch plays out differently but reproducibly
+    ///
+    pub fn new(rom: &[u8], count: usize, quirks: Quirks, seed: u64) -> Self {
+        let machines = (0..count)
+            .map(|n| {
+                let mut chip = Chip8::new(None);
+                chip.set_quirks(quirks);
+                chip.seed_rng(seed.wrapping_add(n as u64));
+                chip.load_rom_bytes(rom);
+                chip
+            })
+            .collect();
+
+        Batch { machines }
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    pub fn machines(&self) -> &[Chip8] {
+        &self.machines
+    }
+
+    pub fn machines_mut(&mut self) -> &mut [Chip8] {
+        &mut self.machines
+    }
+
+    ///
+    /// Sets the held buttons of every machine from one bitmask per machine,
+    /// bit n set meaning button n is down
+    ///
+    pub fn set_keys(&mut self, keys: &[u16]) {
+        assert_eq!(keys.len(), self.machines.len(), "one key mask per machine");
+
+        for (chip, &mask) in self.machines.iter_mut().zip(keys) {
+            for button in 0..16 {
+                chip.key_press(button, mask & (1 << button) != 0);
+            }
+        }
+    }
+
+    ///
+    /// Executes `cycles` instructions on every machine
+    ///
+    pub fn step(&mut self, cycles: u32) {
+        self.machines
+            .par_iter_mut()
+            .for_each(|chip| chip.run_instructions(cycles as u64));
+    }
+
+    ///
+    /// Runs `frames` frames of `ticks_per_frame` instructions followed by a timer tick
+    /// on every machine
+    ///
+    pub fn run_frames(&mut self, frames: u32, ticks_per_frame: u32) {
+        self.machines.par_iter_mut().for_each(|chip| {
+            for _ in 0..frames {
+                chip.run_instructions(ticks_per_frame as u64);
+                chip.tick_timers();
+            }
+        });
+    }
+
+    ///
+    /// Returns the screens of all machines as consecutive packed framebuffers
+    ///
+    pub fn framebuffers(&self) -> Vec<u8> {
+        let mut buffer = vec![0; self.machines.len() * PACKED_FRAME_SIZE];
+        self.framebuffers_into(&mut buffer);
+        buffer
+    }
+
+    ///
+    /// Writes the packed framebuffers of all machines into `buffer`, which must hold
+    /// `len() * PACKED_FRAME_SIZE` bytes. Lets callers reuse one buffer across steps
+    ///
+    pub fn framebuffers_into(&self, buffer: &mut [u8]) {
+        assert_eq!(
+            buffer.len(),
+            self.machines.len() * PACKED_FRAME_SIZE,
+            "buffer must hold one packed frame per machine"
+        );
+
+        buffer
+            .par_chunks_mut(PACKED_FRAME_SIZE)
+            .zip(&self.machines)
+            .for_each(|(frame, chip)| chip.display().pack_into(frame));
+    }
+}
@@ -0,0 +1,338 @@
+//!
+//! Configuration of new machines. `Chip8::new` creates the common setup: 4K of RAM with
+//! the COSMAC VIP font at 0x000, programs starting at 0x200 and a stack of 16 return
+//! addresses.
+//! The builder changes any of these, e.g. for ETI-660 ROMs, which start at 0x600:
+//!
+//! ```
+//! use chip8_core::{builder::Chip8Builder, quirks::Platform};
+//!
+//! let chip = Chip8Builder::new()
+//!     .platform(Platform::CosmacVip)
+//!     .start_address(0x600)
+//!     .seed(42)
+//!     .build()
+//!     .unwrap();
+//!
+//! assert_eq!(chip.program_counter(), 0x600);
+//! ```
+//!
+//! Settings are only checked by `build`, which reports the first one that doesn't work.
+//!
+use crate::{
+    audio::Beeper,
+    decode::DecodeCache,
+    display::Display,
+    fill_memory,
+    font::{Font, FontSet},
+    quirks::{Platform, Quirks},
+    rng::{RandomSource, Rng},
+    Chip8, NUM_REGS, RAM_SIZE, START_ADDRESS,
+};
+
+/// Addresses are 16 bits wide, so larger memories couldn't be reached
+const MAX_RAM_SIZE: usize = 0x10000;
+
+/// Save states store the depth of the stack in a byte
+const MAX_STACK_DEPTH: usize = u8::MAX as usize;
+
+const DEFAULT_STACK_DEPTH: usize = 16;
+
+pub struct Chip8Builder {
+    quirks: Quirks,
+    ram_size: usize,
+    start_address: u16,
+    stack_depth: usize,
+    font: Font,
+    font_address: u16,
+    memory_fill: Vec<u8>,
+    seed: Option<u64>,
+    random_source: RandomSource,
+    audio: Option<Box<dyn Beeper>>,
+}
+
+impl Chip8Builder {
+    ///
+    /// Starts from the settings `Chip8::new` uses
+    ///
+    pub fn new() -> Self {
+        Chip8Builder {
+            quirks: Quirks::default(),
+            ram_size: RAM_SIZE,
+            start_address: START_ADDRESS,
+            stack_depth: DEFAULT_STACK_DEPTH,
+            font: Font::default(),
+            font_address: 0x000,
+            memory_fill: vec![0],
+            seed: None,
+            random_source: RandomSource::default(),
+            audio: None,
+        }
+    }
+
+    ///
+    /// Uses the quirks of the given interpreter
+    ///
+    pub fn platform(self, platform: Platform) -> Self {
+        self.quirks(platform.quirks())
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    ///
+    /// Bytes of RAM, a power of two so addresses wrap around at the end of memory.
+    /// At most 64K
+    ///
+    pub fn ram_size(mut self, size: usize) -> Self {
+        self.ram_size = size;
+        self
+    }
+
+    ///
+    /// Address ROMs are loaded at and execution starts from. Memory from here to the end
+    /// of RAM belongs to the program, so the font must not reach into it
+    ///
+    pub fn start_address(mut self, address: u16) -> Self {
+        self.start_address = address;
+        self
+    }
+
+    ///
+    /// Number of return addresses the stack holds before the oldest ones are dropped, up to 255
+    ///
+    pub fn stack_depth(mut self, depth: usize) -> Self {
+        self.stack_depth = depth;
+        self
+    }
+
+    ///
+    /// Glyphs drawn by `FX29` and `FX30`, see `font_set` for the built-in ones
+    ///
+    pub fn font(mut self, font: Font) -> Self {
+        self.font = font;
+        self
+    }
+
+    pub fn font_set(self, set: FontSet) -> Self {
+        self.font(set.font())
+    }
+
+    ///
+    /// Where the font is loaded, 0x000 by default. Some interpreters kept it elsewhere,
+    /// e.g. at 0x050, and ROMs reading the glyphs directly expect them there
+    ///
+    pub fn font_address(mut self, address: u16) -> Self {
+        self.font_address = address;
+        self
+    }
+
+    ///
+    /// Bytes repeated across RAM when the machine is created and on `Chip8::hard_reset`,
+    /// zeroes by default. Some ROMs read memory they never wrote, which real hardware
+    /// powered on with arbitrary contents
+    ///
+    pub fn memory_fill(mut self, pattern: &[u8]) -> Self {
+        self.memory_fill = pattern.to_vec();
+        self
+    }
+
+    ///
+    /// Seed of the random number generator used by `CXNN`, taken from the operating
+    /// system's entropy if not set
+    ///
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn random_source(mut self, source: RandomSource) -> Self {
+        self.random_source = source;
+        self
+    }
+
+    ///
+    /// Sounds the beeper through the given backend. Machines without one run silently
+    ///
+    pub fn audio(mut self, beeper: Box<dyn Beeper>) -> Self {
+        self.audio = Some(beeper);
+        self
+    }
+
+    pub fn build(self) -> Result<Chip8, String> {
+        if !self.ram_size.is_power_of_two() || self.ram_size > MAX_RAM_SIZE {
+            return Err(format!(
+                "RAM size {} is not a power of two up to {}",
+                self.ram_size, MAX_RAM_SIZE
+            ));
+        }
+        if self.memory_fill.is_empty() {
+            return Err("memory fill pattern is empty".to_string());
+        }
+        let start = self.start_address as usize;
+        if start >= self.ram_size {
+            return Err(format!(
+                "start address {:#05x} lies past the end of RAM ({:#05x})",
+                self.start_address, self.ram_size
+            ));
+        }
+        let font = self.font_address as usize..self.font_address as usize + self.font.size();
+        if font.end > self.ram_size {
+            return Err(format!(
+                "font at {:#05x} to {:#05x} doesn't fit in RAM ({:#05x})",
+                font.start, font.end, self.ram_size
+            ));
+        }
+        // Programs may use all memory from the start address on
+        if font.end > start {
+            return Err(format!(
+                "font at {:#05x} to {:#05x} overlaps the program area starting at {:#05x}",
+                font.start, font.end, self.start_address
+            ));
+        }
+        if !(1..=MAX_STACK_DEPTH).contains(&self.stack_depth) {
+            return Err(format!(
+                "stack depth {} is not between 1 and {}",
+                self.stack_depth, MAX_STACK_DEPTH
+            ));
+        }
+
+        let mut ram = vec![0; self.ram_size].into_boxed_slice();
+        fill_memory(&mut ram, &self.memory_fill);
+
+        let seed = self.seed.unwrap_or_else(|| Rng::from_entropy().seed());
+
+        let mut chip = Chip8 {
+            program_counter: self.start_address,
+            ram,
+            address_mask: self.ram_size - 1,
+            start_address: self.start_address,
+            font: self.font,
+            font_address: self.font_address,
+            memory_fill: self.memory_fill,
+            display: Display::new(),
+            decoded: DecodeCache::new(self.ram_size),
+            v_registers: [0; NUM_REGS],
+            i_register: 0,
+            stack_pointer: 0,
+            stack: vec![0; self.stack_depth].into_boxed_slice(),
+            delay_timer: 0,
+            sound_timer: 0,
+            keyboard: [false; 16],
+            audio: self.audio,
+            beeping: false,
+            rng: Rng::with_source(self.random_source, seed),
+            rom: Vec::new(),
+            frames: 0,
+            input_log: None,
+            hooks: None,
+            memory_writes: None,
+            quirks: self.quirks,
+        };
+        chip.load_font();
+
+        Ok(chip)
+    }
+}
+
+impl Default for Chip8Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_configures_the_memory_layout() {
+        let mut chip = Chip8::builder()
+            .ram_size(0x2000)
+            .start_address(0x600)
+            .stack_depth(2)
+            .build()
+            .unwrap();
+        assert_eq!(chip.memory().len(), 0x2000);
+        assert_eq!(chip.stack_depth(), 2);
+
+        chip.load_rom_bytes(&[
+            0x26, 0x04, // 600: call 604
+            0x00, 0x00, // 602
+            0x26, 0x08, // 604: call 608
+            0x00, 0x00, // 606
+            0x26, 0x00, // 608: call 600, dropping the oldest return address
+        ]);
+        assert_eq!(chip.program_counter(), 0x600);
+
+        chip.run_instructions(3);
+        assert_eq!(chip.program_counter(), 0x600);
+        assert_eq!(chip.stack(), [0x606, 0x60A]);
+
+        // Addresses wrap at the end of the larger RAM
+        chip.set_i_register(0x1FFF);
+        chip.set_v_register(0, 0x42).unwrap();
+        chip.execute(0xF155);
+        assert_eq!(chip.memory()[0x1FFF], 0x42);
+        assert_eq!(chip.memory()[0], 0);
+    }
+
+    #[test]
+    fn builder_loads_a_custom_font() {
+        let bytes: Vec<u8> = (0..80).collect();
+        let font = Font::from_bytes(&bytes).unwrap();
+        let mut chip = Chip8::builder().font(font).build().unwrap();
+
+        chip.set_v_register(0, 0xA).unwrap();
+        chip.execute(0xF029);
+        assert_eq!(chip.memory()[chip.i_register() as usize], 50);
+        assert_eq!(chip.memory()[..80], bytes[..]);
+    }
+
+    #[test]
+    fn builder_seeds_the_random_number_generator() {
+        let build = || Chip8::builder().seed(7).build().unwrap();
+        let (mut first, mut second) = (build(), build());
+        assert_eq!(first.rng_seed(), 7);
+
+        for _ in 0..8 {
+            first.execute(0xC0FF);
+            second.execute(0xC0FF);
+            assert_eq!(first.v_registers()[0], second.v_registers()[0]);
+        }
+    }
+
+    #[test]
+    fn random_numbers_work_on_the_smallest_ram() {
+        // 128 bytes only hold the font and a program starting right after it
+        let mut chip = Chip8::builder()
+            .ram_size(0x80)
+            .start_address(0x50)
+            .random_source(RandomSource::CosmacVip)
+            .build()
+            .unwrap();
+        chip.load_rom_bytes(&[0xC0, 0xFF, 0x10, 0x50]);
+
+        // Every pointer value of the VIP routine comes up within 256 random numbers
+        chip.run_instructions(600);
+        assert_eq!(chip.program_counter(), 0x50);
+    }
+
+    #[test]
+    fn builder_rejects_invalid_settings() {
+        for builder in [
+            Chip8::builder().ram_size(3000),
+            Chip8::builder().ram_size(0x20000),
+            Chip8::builder().start_address(0x40),
+            Chip8::builder().ram_size(0x400).start_address(0x600),
+            Chip8::builder().stack_depth(0),
+            Chip8::builder().stack_depth(256),
+            Chip8::builder().font_address(0x1D0),
+            Chip8::builder().memory_fill(&[]),
+        ] {
+            assert!(builder.build().is_err());
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//!
+//! Cheats: a RAM search for finding where a game keeps values like lives or the score,
+//! and cheat codes that write fixed bytes to memory every frame.
+//!
+//! Cheat codes are stored per ROM in a text file with one `<name> = <address>: <bytes>`
+//! code per line, all numbers in hex:
+//!
+//! ```text
+//! # Brix
+//! lives = 2F4: 05
+//! skip-intro = 23A: 12 60
+//! ```
+//!
+//! A single byte freezes a value, longer sequences patch code or data.
+//!
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::Chip8;
+
+///
+/// How the values of the remaining candidates have to compare to their previous values
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchFilter {
+    /// The value equals the given one
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl SearchFilter {
+    fn matches(&self, previous: u8, current: u8) -> bool {
+        match *self {
+            SearchFilter::Equal(value) => current == value,
+            SearchFilter::Changed => current != previous,
+            SearchFilter::Unchanged => current == previous,
+            SearchFilter::Increased => current > previous,
+            SearchFilter::Decreased => current < previous,
+        }
+    }
+}
+
+///
+/// Narrows down the addresses holding a value by repeatedly comparing RAM to the previous
+/// snapshot, e.g. filtering by `Decreased` every time a life is lost
+///
+pub struct RamSearch {
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+impl RamSearch {
+    ///
+    /// Starts a search with every address as a candidate
+    ///
+    pub fn new(chip: &Chip8) -> Self {
+        RamSearch {
+            snapshot: chip.memory().to_vec(),
+            candidates: (0..chip.memory().len())
+                .map(|address| address as u16)
+                .collect(),
+        }
+    }
+
+    ///
+    /// Keeps the candidates whose value passes the filter and takes a new snapshot.
+    /// Returns the number of remaining candidates
+    ///
+    pub fn filter(&mut self, chip: &Chip8, filter: SearchFilter) -> usize {
+        let memory = chip.memory();
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&address| {
+            let address = address as usize;
+            filter.matches(snapshot[address], memory[address])
+        });
+        self.snapshot.copy_from_slice(memory);
+
+        self.candidates.len()
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    ///
+    /// Value of an address when the last snapshot was taken
+    ///
+    pub fn value(&self, address: u16) -> u8 {
+        self.snapshot[address as usize]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    pub name: String,
+    pub address: u16,
+    pub bytes: Vec<u8>,
+}
+
+impl Cheat {
+    pub fn new(name: &str, address: u16, bytes: Vec<u8>) -> Result<Self, String> {
+        if name.is_empty() || name.contains(char::is_whitespace) || name.contains('=') {
+            return Err(format!("invalid cheat name '{}'", name));
+        }
+        if bytes.is_empty() || address as usize + bytes.len() > 0x10000 {
+            return Err(format!(
+                "cheat '{}' must write at least one byte and stay within the address space",
+                name
+            ));
+        }
+
+        Ok(Cheat {
+            name: name.to_string(),
+            address,
+            bytes,
+        })
+    }
+
+    ///
+    /// Writes the bytes to memory, failing if they don't fit in the machine's RAM
+    ///
+    pub fn apply(&self, chip: &mut Chip8) -> Result<(), String> {
+        chip.write_memory(self.address, &self.bytes)
+            .map_err(|error| format!("cheat '{}': {}", self.name, error))
+    }
+}
+
+impl FromStr for Cheat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("expected '<name> = <address>: <bytes>' but got '{}'", s);
+
+        let (name, code) = s.split_once('=').ok_or_else(error)?;
+        let (address, bytes) = code.split_once(':').ok_or_else(error)?;
+        let address = u16::from_str_radix(address.trim(), 16).map_err(|_| error())?;
+        let bytes = bytes
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| error())?;
+
+        Cheat::new(name.trim(), address, bytes)
+    }
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {:03X}:", self.name, self.address)?;
+        for byte in &self.bytes {
+            write!(f, " {:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// The cheat codes active for a ROM
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Cheats> {
+        let contents = fs::read_to_string(path)?;
+        Cheats::parse(&contents).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    pub fn parse(contents: &str) -> Result<Cheats, String> {
+        let mut cheats = Cheats::default();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let cheat = line
+                .parse()
+                .map_err(|error| format!("line {}: {}", number + 1, error))?;
+            cheats.add(cheat);
+        }
+
+        Ok(cheats)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+        for cheat in &self.cheats {
+            writeln!(writer, "{}", cheat)?;
+        }
+        writer.flush()
+    }
+
+    ///
+    /// Adds a cheat, replacing the one with the same name
+    ///
+    pub fn add(&mut self, cheat: Cheat) {
+        match self
+            .cheats
+            .iter_mut()
+            .find(|other| other.name == cheat.name)
+        {
+            Some(other) => *other = cheat,
+            None => self.cheats.push(cheat),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Cheat> {
+        let index = self.cheats.iter().position(|cheat| cheat.name == name)?;
+        Some(self.cheats.remove(index))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cheat> {
+        self.cheats.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    ///
+    /// Writes the bytes of every cheat to memory. Call this once per frame
+    ///
+    pub fn apply(&self, chip: &mut Chip8) -> Result<(), String> {
+        self.cheats.iter().try_for_each(|cheat| cheat.apply(chip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cheats_must_fit_in_the_machines_ram() {
+        let cheats = Cheats::parse("lives = FFF: 05\nscore = 1FFF: 09").unwrap();
+        assert!(cheats.apply(&mut Chip8::new(None)).is_err());
+
+        let mut chip = Chip8::builder().ram_size(0x2000).build().unwrap();
+        cheats.apply(&mut chip).unwrap();
+        assert_eq!(chip.memory()[0x1FFF], 0x09);
+
+        assert!("wrap = FFFF: 01 02".parse::<Cheat>().is_err());
+    }
+}
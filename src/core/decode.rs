@@ -0,0 +1,198 @@
+//!
+//! Instruction decoding and the cache of decoded instructions. Decoding works out which
+//! handler an opcode belongs to once, so running an instruction from the cache is a single
+//! jump instead of fetching two bytes and matching on every digit.
+//!
+//! Entries are dropped whenever a byte of the instruction they were decoded from is
+//! written, which keeps self-modifying ROMs exactly compatible.
+//!
+///
+/// The handler an opcode is dispatched to
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Kind {
+    /// Not decoded yet, only used by cache entries
+    #[default]
+    Undecoded,
+    Nop,
+    Op00e0,
+    Op00ee,
+    Op1nnn,
+    Op2nnn,
+    Op3xnn,
+    Op4xnn,
+    Op5xy0,
+    Op6xnn,
+    Op7xnn,
+    Op8xy0,
+    Op8xy1,
+    Op8xy2,
+    Op8xy3,
+    Op8xy4,
+    Op8xy5,
+    Op8xy6,
+    Op8xy7,
+    Op8xye,
+    Op9xy0,
+    Opannn,
+    Opbnnn,
+    Opcxnn,
+    Opdxyn,
+    Opex9e,
+    Opexa1,
+    Opfx07,
+    Opfx0a,
+    Opfx15,
+    Opfx18,
+    Opfx1e,
+    Opfx29,
+    Opfx30,
+    Opfx33,
+    Opfx55,
+    Opfx65,
+    Unknown,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Decoded {
+    pub kind: Kind,
+    pub op: u16,
+}
+
+pub(crate) fn decode(op: u16) -> Decoded {
+    // Extract the digits from the opcode
+    let digit1 = (op & 0xF000) >> 12;
+    let digit2 = (op & 0x0F00) >> 8;
+    let digit3 = (op & 0x00F0) >> 4;
+    let digit4 = op & 0x000F;
+
+    let kind = match (digit1, digit2, digit3, digit4) {
+        (0, 0, 0, 0) => Kind::Nop,
+        (0, 0, 0xE, 0) => Kind::Op00e0,
+        (0, 0, 0xE, 0xE) => Kind::Op00ee,
+        (1, _, _, _) => Kind::Op1nnn,
+        (2, _, _, _) => Kind::Op2nnn,
+        (3, _, _, _) => Kind::Op3xnn,
+        (4, _, _, _) => Kind::Op4xnn,
+        (5, _, _, 0) => Kind::Op5xy0,
+        (6, _, _, _) => Kind::Op6xnn,
+        (7, _, _, _) => Kind::Op7xnn,
+        (8, _, _, 0) => Kind::Op8xy0,
+        (8, _, _, 1) => Kind::Op8xy1,
+        (8, _, _, 2) => Kind::Op8xy2,
+        (8, _, _, 3) => Kind::Op8xy3,
+        (8, _, _, 4) => Kind::Op8xy4,
+        (8, _, _, 5) => Kind::Op8xy5,
+        (8, _, _, 6) => Kind::Op8xy6,
+        (8, _, _, 7) => Kind::Op8xy7,
+        (8, _, _, 0xE) => Kind::Op8xye,
+        (9, _, _, 0) => Kind::Op9xy0,
+        (0xA, _, _, _) => Kind::Opannn,
+        (0xB, _, _, _) => Kind::Opbnnn,
+        (0xC, _, _, _) => Kind::Opcxnn,
+        (0xD, _, _, _) => Kind::Opdxyn,
+        (0xE, _, 9, 0xE) => Kind::Opex9e,
+        (0xE, _, 0xA, 1) => Kind::Opexa1,
+        (0xF, _, 0, 7) => Kind::Opfx07,
+        (0xF, _, 0, 0xA) => Kind::Opfx0a,
+        (0xF, _, 1, 5) => Kind::Opfx15,
+        (0xF, _, 1, 8) => Kind::Opfx18,
+        (0xF, _, 1, 0xE) => Kind::Opfx1e,
+        (0xF, _, 2, 9) => Kind::Opfx29,
+        (0xF, _, 3, 0) => Kind::Opfx30,
+        (0xF, _, 3, 3) => Kind::Opfx33,
+        (0xF, _, 5, 5) => Kind::Opfx55,
+        (0xF, _, 6, 5) => Kind::Opfx65,
+        (_, _, _, _) => Kind::Unknown,
+    };
+
+    Decoded { kind, op }
+}
+
+///
+/// Decoded instructions keyed by the address they start at
+///
+pub(crate) struct DecodeCache {
+    entries: Box<[Decoded]>,
+}
+
+impl DecodeCache {
+    pub fn new(ram_size: usize) -> Self {
+        DecodeCache {
+            entries: vec![Decoded::default(); ram_size].into_boxed_slice(),
+        }
+    }
+
+    ///
+    /// The instruction decoded at `address`, whose kind is `Undecoded` if there is none
+    ///
+    pub fn get(&self, address: usize) -> Decoded {
+        self.entries[address]
+    }
+
+    pub fn insert(&mut self, address: usize, decoded: Decoded) {
+        self.entries[address] = decoded;
+    }
+
+    ///
+    /// Drops the instructions containing the byte at `address`: the one starting there
+    /// and the one starting at the byte before
+    ///
+    pub fn invalidate(&mut self, address: usize) {
+        self.entries[address] = Decoded::default();
+        let len = self.entries.len();
+        self.entries[(address + len - 1) % len] = Decoded::default();
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(Decoded::default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip8;
+
+    #[test]
+    fn writes_invalidate_both_instructions_containing_the_byte() {
+        let mut cache = DecodeCache::new(0x1000);
+        for address in [0x1FF, 0x200, 0x201, 0xFFF] {
+            cache.insert(address, decode(0x6001));
+        }
+
+        cache.invalidate(0x200);
+        assert_eq!(cache.get(0x1FF).kind, Kind::Undecoded);
+        assert_eq!(cache.get(0x200).kind, Kind::Undecoded);
+        assert_eq!(cache.get(0x201).kind, Kind::Op6xnn);
+
+        // The instruction starting at the last byte of RAM wraps around to the first
+        cache.invalidate(0x000);
+        assert_eq!(cache.get(0xFFF).kind, Kind::Undecoded);
+    }
+
+    #[test]
+    fn cached_instructions_see_self_modifying_code() {
+        let mut chip = Chip8::new(None);
+        chip.load_rom_bytes(&[
+            0x60, 0x01, // 200: V0 = 1
+            0x61, 0x11, // 202: V1 = 11
+            0xA2, 0x03, // 204: I = 203
+            0xF0, 0x55, // 206: store V0 at 203, making 202 read V1 = 01
+            0x12, 0x02, // 208: jump 202
+        ]);
+
+        chip.run_instructions(5);
+        assert_eq!(chip.v_registers[1], 0x11);
+
+        // 202 was decoded and cached before FX55 rewrote its second byte
+        chip.run_instructions(1);
+        assert_eq!(chip.v_registers[1], 0x01);
+
+        // Writes from outside the interpreter drop cached instructions too
+        chip.write_memory(0x203, &[0x22]).unwrap();
+        chip.set_program_counter(0x202);
+        chip.run_instructions(1);
+        assert_eq!(chip.v_registers[1], 0x22);
+    }
+}
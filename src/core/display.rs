@@ -0,0 +1,245 @@
+//!
+//! The monochrome framebuffer, packed one bit per pixel with a `u64` per row. The leftmost
+//! pixel of a row is its most significant bit, so a sprite byte lines up with a row by
+//! shifting and whole rows are drawn and checked for collisions at once.
+//!
+//! Rows that change are flagged as dirty, so frontends only redraw when and where the
+//! picture actually changed.
+//!
+//! The layout only fits the 64x32 low resolution screen. The 128x64 mode of SUPER-CHIP
+//! needs its `00FF` and `00FE` instructions, which the interpreter doesn't implement yet,
+//! and will need wider rows and a larger dirty bitmap along with them.
+//!
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// A row per u64 and a dirty bit per row in a u32
+const _: () = assert!(SCREEN_WIDTH == 64 && SCREEN_HEIGHT <= 32);
+
+///
+/// Bytes needed to store the screen packed, see `Display::pack_into`
+///
+pub const PACKED_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT / 8;
+
+/// Dirty bitmap with every row flagged
+const ALL_ROWS: u32 = u32::MAX >> (32 - SCREEN_HEIGHT);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Display {
+    rows: [u64; SCREEN_HEIGHT],
+    // Bit y is set when row y changed since the dirty rows were last taken
+    dirty_rows: u32,
+}
+
+impl Display {
+    ///
+    /// Creates a blank display. Every row starts out dirty, so the first picture gets drawn
+    ///
+    pub fn new() -> Self {
+        Display {
+            rows: [0; SCREEN_HEIGHT],
+            dirty_rows: ALL_ROWS,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for (y, row) in self.rows.iter_mut().enumerate() {
+            if *row != 0 {
+                *row = 0;
+                self.dirty_rows |= 1 << y;
+            }
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        SCREEN_WIDTH
+    }
+
+    pub fn height(&self) -> usize {
+        SCREEN_HEIGHT
+    }
+
+    ///
+    /// Whether the pixel at (x, y) is lit. Panics if the position is off screen
+    ///
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        assert!(x < SCREEN_WIDTH, "x {} is off screen", x);
+        self.rows[y] & column_bit(x) != 0
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, lit: bool) {
+        assert!(x < SCREEN_WIDTH, "x {} is off screen", x);
+        let row = if lit {
+            self.rows[y] | column_bit(x)
+        } else {
+            self.rows[y] & !column_bit(x)
+        };
+
+        if row != self.rows[y] {
+            self.rows[y] = row;
+            self.dirty_rows |= 1 << y;
+        }
+    }
+
+    ///
+    /// Bitmap of the rows changed since `take_dirty_rows` was last called, bit y standing for row y
+    ///
+    pub fn dirty_rows(&self) -> u32 {
+        self.dirty_rows
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty_rows != 0
+    }
+
+    ///
+    /// Returns the dirty rows and marks every row clean. Meant for the one frontend drawing
+    /// the screen, other readers only look at `dirty_rows`
+    ///
+    pub fn take_dirty_rows(&mut self) -> u32 {
+        std::mem::take(&mut self.dirty_rows)
+    }
+
+    ///
+    /// The pixels of row `y` as bits, the leftmost pixel in the most significant bit
+    ///
+    pub fn row(&self, y: usize) -> u64 {
+        self.rows[y]
+    }
+
+    pub fn rows(&self) -> &[u64; SCREEN_HEIGHT] {
+        &self.rows
+    }
+
+    ///
+    /// Every pixel, row by row from the top left
+    ///
+    pub fn pixels(&self) -> impl Iterator<Item = bool> + '_ {
+        self.rows
+            .iter()
+            .flat_map(|&row| (0..SCREEN_WIDTH).map(move |x| row & column_bit(x) != 0))
+    }
+
+    ///
+    /// Builds a display from pixels in the order `pixels` returns them. Panics unless
+    /// there is exactly one value per pixel
+    ///
+    pub fn from_pixels(pixels: &[bool]) -> Self {
+        assert_eq!(
+            pixels.len(),
+            SCREEN_WIDTH * SCREEN_HEIGHT,
+            "one value per pixel"
+        );
+
+        let mut display = Display::new();
+        for (row, line) in display
+            .rows
+            .iter_mut()
+            .zip(pixels.chunks_exact(SCREEN_WIDTH))
+        {
+            *row = line
+                .iter()
+                .fold(0, |bits, &pixel| (bits << 1) | pixel as u64);
+        }
+        display
+    }
+
+    ///
+    /// Writes the screen one bit per pixel into `buffer`, which must hold `PACKED_SIZE`
+    /// bytes: rows of 8 bytes with the leftmost pixel in the most significant bit
+    ///
+    pub fn pack_into(&self, buffer: &mut [u8]) {
+        assert_eq!(
+            buffer.len(),
+            PACKED_SIZE,
+            "buffer must hold a packed screen"
+        );
+
+        for (bytes, row) in buffer.chunks_exact_mut(8).zip(&self.rows) {
+            bytes.copy_from_slice(&row.to_be_bytes());
+        }
+    }
+
+    ///
+    /// XORs a sprite onto the screen with its top left corner at (x, y), one byte per row.
+    /// The position wraps around the screen; parts of the sprite going over the edges are
+    /// clipped or wrap around too. Returns whether any lit pixel was turned off
+    ///
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8], clip: bool) -> bool {
+        let x = (x % SCREEN_WIDTH) as u32;
+        let y = y % SCREEN_HEIGHT;
+        let mut collision = false;
+
+        for (line, &byte) in sprite.iter().enumerate() {
+            let row = y + line;
+            if row >= SCREEN_HEIGHT && clip {
+                break;
+            }
+
+            let sprite_row = (byte as u64) << (SCREEN_WIDTH - 8);
+            let bits = if clip {
+                sprite_row >> x
+            } else {
+                sprite_row.rotate_right(x)
+            };
+
+            if bits == 0 {
+                continue;
+            }
+
+            let row = row % SCREEN_HEIGHT;
+            collision |= self.rows[row] & bits != 0;
+            self.rows[row] ^= bits;
+            self.dirty_rows |= 1 << row;
+        }
+
+        collision
+    }
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Display::new()
+    }
+}
+
+///
+/// Displays are equal when they show the same picture, whatever rows are dirty
+///
+impl PartialEq for Display {
+    fn eq(&self, other: &Self) -> bool {
+        self.rows == other.rows
+    }
+}
+
+impl Eq for Display {}
+
+fn column_bit(x: usize) -> u64 {
+    1 << (SCREEN_WIDTH - 1 - x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drawing_and_clearing_flag_the_changed_rows() {
+        let mut display = Display::new();
+        assert_eq!(display.take_dirty_rows(), ALL_ROWS);
+
+        // Clearing a blank screen changes nothing
+        display.clear();
+        assert_eq!(display.take_dirty_rows(), 0);
+
+        // 5 rows high, drawn wrapping from row 30 to row 2
+        display.draw_sprite(5, 30, &[0x20, 0x60, 0x20, 0x20, 0x70], false);
+        assert_eq!(display.take_dirty_rows(), 0b111 | (0b11 << 30));
+
+        // Pixels set to the value they already have don't dirty their row
+        display.set_pixel(7, 0, true);
+        display.set_pixel(0, 0, false);
+        assert!(!display.is_dirty());
+
+        display.clear();
+        assert_eq!(display.take_dirty_rows(), 0b111 | (0b11 << 30));
+    }
+}
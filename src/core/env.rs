@@ -0,0 +1,453 @@
+//!
+//! Gym-style environment for training agents on CHIP-8 games, without a window or sound.
+//!
+//! Each step holds the buttons of the action down for a number of frames and returns the
+//! screen, a reward and whether the episode is over. Rewards and the end of an episode are
+//! read from the machine with per-ROM probes, e.g. the score a game keeps in memory.
+//!
+//! Probes can be described in a file with one `<name> = <value>` setting per line:
+//!
+//! ```text
+//! # Brix: the score is stored by FX33 at 0x2F0, VE holds the lives
+//! reward = bcd:2F0:3
+//! done = vE == 0
+//! ```
+//!
+use std::{fs, str::FromStr};
+
+use crate::{
+    quirks::Platform,
+    rng::RandomSource,
+    scheduler::{DEFAULT_INSTRUCTIONS_PER_SECOND, TIMER_FREQUENCY},
+    Chip8, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+/// Most digits of a BCD probe, the largest such number fits in a `u32`
+const MAX_BCD_DIGITS: u8 = 9;
+
+///
+/// A value read from the machine
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Probe {
+    /// Byte in RAM, written `ram:<hex address>`
+    Ram(u16),
+    /// Decimal number stored one digit per byte as written by `FX33`, most significant
+    /// digit first, written `bcd:<hex address>:<digits>` with up to 9 digits
+    Bcd { address: u16, digits: u8 },
+    /// Register Vx, written `v<x>` with a hex digit x, e.g. `vE`
+    Register(u8),
+}
+
+impl Probe {
+    pub fn read(&self, chip: &Chip8) -> u32 {
+        match *self {
+            Probe::Ram(address) => chip.read_ram(address as usize) as u32,
+            // Bytes above 9 aren't digits, saturate instead of overflowing on them
+            Probe::Bcd { address, digits } => (0..digits as usize).fold(0u32, |value, digit| {
+                let byte = chip.read_ram(address as usize + digit) as u32;
+                value.saturating_mul(10).saturating_add(byte)
+            }),
+            Probe::Register(x) => chip.v_registers[x as usize] as u32,
+        }
+    }
+
+    ///
+    /// Checks that the memory the probe reads exists on the given machine
+    ///
+    pub fn check(&self, chip: &Chip8) -> Result<(), String> {
+        let (address, len) = match *self {
+            Probe::Ram(address) => (address, 1),
+            Probe::Bcd { address, digits } => (address, digits as usize),
+            Probe::Register(_) => return Ok(()),
+        };
+
+        if address as usize + len > chip.memory().len() {
+            return Err(format!(
+                "probe reads past the end of RAM at {:#05x}",
+                chip.memory().len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Probe {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || {
+            format!(
+                "invalid probe '{}' (expected ram:<address>, bcd:<address>:<digits> or v<register>)",
+                s
+            )
+        };
+        let address = |hex: &str| u16::from_str_radix(hex, 16).map_err(|_| error());
+
+        let fields: Vec<_> = s.split(':').collect();
+        match fields[..] {
+            ["ram", hex] => Ok(Probe::Ram(address(hex)?)),
+            ["bcd", hex, digits] => {
+                let address = address(hex)?;
+                let digits: u8 = digits.parse().map_err(|_| error())?;
+                if !(1..=MAX_BCD_DIGITS).contains(&digits)
+                    || address as usize + digits as usize > 0x10000
+                {
+                    return Err(error());
+                }
+                Ok(Probe::Bcd { address, digits })
+            }
+            [register] => register
+                .strip_prefix(['v', 'V'])
+                .filter(|x| x.len() == 1)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                .map(Probe::Register)
+                .ok_or_else(error),
+            _ => Err(error()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+}
+
+///
+/// A probe compared against a constant, e.g. `vE == 0`
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub probe: Probe,
+    pub comparison: Comparison,
+    pub value: u32,
+}
+
+impl Condition {
+    pub fn holds(&self, chip: &Chip8) -> bool {
+        let actual = self.probe.read(chip);
+        match self.comparison {
+            Comparison::Equal => actual == self.value,
+            Comparison::NotEqual => actual != self.value,
+            Comparison::Less => actual < self.value,
+            Comparison::Greater => actual > self.value,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<_> = s.split_whitespace().collect();
+        let [probe, comparison, value] = fields[..] else {
+            return Err(format!(
+                "invalid condition '{}' (expected '<probe> <==|!=|<|>> <value>')",
+                s
+            ));
+        };
+
+        let comparison = match comparison {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            ">" => Comparison::Greater,
+            _ => return Err(format!("unknown comparison '{}'", comparison)),
+        };
+
+        Ok(Condition {
+            probe: probe.parse()?,
+            comparison,
+            value: value
+                .parse()
+                .map_err(|_| format!("invalid value '{}'", value))?,
+        })
+    }
+}
+
+///
+/// Where the environment reads rewards and the end of an episode from
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Probes {
+    /// The reward of a step is the change of this value during the step
+    pub reward: Option<Probe>,
+    /// The episode ends once this condition holds
+    pub done: Option<Condition>,
+}
+
+impl Probes {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read probes '{}': {}", path, e))?;
+        Probes::parse(&contents).map_err(|error| format!("{}:{}", path, error))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut probes = Probes::default();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |message: String| format!("{}: {}", number + 1, message);
+            match line.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                Some(("reward", probe)) => probes.reward = Some(probe.parse().map_err(error)?),
+                Some(("done", condition)) => probes.done = Some(condition.parse().map_err(error)?),
+                _ => {
+                    return Err(error(
+                        "expected 'reward = <probe>' or 'done = <condition>'".to_string(),
+                    ))
+                }
+            }
+        }
+
+        Ok(probes)
+    }
+
+    pub fn check(&self, chip: &Chip8) -> Result<(), String> {
+        if let Some(probe) = &self.reward {
+            probe.check(chip)?;
+        }
+        if let Some(condition) = &self.done {
+            condition.probe.check(chip)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnvConfig {
+    pub platform: Platform,
+    pub random: RandomSource,
+    /// Instructions executed per frame
+    pub ticks_per_frame: u32,
+    /// Frames run per step, with the buttons of the action held for all of them
+    pub frame_skip: u32,
+    /// Ends episodes after this many frames
+    pub max_frames: Option<u64>,
+    pub probes: Probes,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        EnvConfig {
+            platform: Platform::default(),
+            random: RandomSource::default(),
+            ticks_per_frame: DEFAULT_INSTRUCTIONS_PER_SECOND / TIMER_FREQUENCY as u32,
+            frame_skip: 4,
+            max_frames: None,
+            probes: Probes::default(),
+        }
+    }
+}
+
+///
+/// A ROM wrapped as an environment. Actions are bitmasks of the held buttons,
+/// bit n set meaning button n is down.
+/// Observations hold one byte per pixel, 1 for lit pixels, row by row.
+///
+pub struct Env {
+    config: EnvConfig,
+    rom: Vec<u8>,
+    chip: Chip8,
+    score: u32,
+}
+
+impl Env {
+    pub const OBSERVATION_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
+
+    ///
+    /// Fails if the probes read memory the machine doesn't have
+    ///
+    pub fn new(rom: &[u8], config: EnvConfig) -> Result<Self, String> {
+        let mut env = Env {
+            config,
+            rom: rom.to_vec(),
+            chip: Chip8::new(None),
+            score: 0,
+        };
+        env.reset(0);
+        env.config.probes.check(&env.chip)?;
+        Ok(env)
+    }
+
+    pub fn config(&self) -> &EnvConfig {
+        &self.config
+    }
+
+    pub fn chip(&self) -> &Chip8 {
+        &self.chip
+    }
+
+    ///
+    /// Starts a new episode on a freshly booted machine and returns the first observation
+    ///
+    pub fn reset(&mut self, seed: u64) -> Vec<u8> {
+        self.chip = Chip8::builder()
+            .platform(self.config.platform)
+            .random_source(self.config.random)
+            .seed(seed)
+            .build()
+            .expect("the default memory layout is valid");
+        self.chip.load_rom_bytes(&self.rom);
+        self.score = self.read_score();
+
+        self.observation()
+    }
+
+    ///
+    /// Holds the buttons of `action` for `frame_skip` frames and returns the observation,
+    /// the reward collected during those frames and whether the episode has ended
+    ///
+    pub fn step(&mut self, action: u16) -> (Vec<u8>, f64, bool) {
+        for button in 0..16 {
+            self.chip.key_press(button, action & (1 << button) != 0);
+        }
+
+        for _ in 0..self.config.frame_skip {
+            self.chip
+                .run_instructions(self.config.ticks_per_frame as u64);
+            self.chip.tick_timers();
+
+            if self.is_done() {
+                break;
+            }
+        }
+
+        let score = self.read_score();
+        let reward = score as f64 - self.score as f64;
+        self.score = score;
+
+        (self.observation(), reward, self.is_done())
+    }
+
+    pub fn observation(&self) -> Vec<u8> {
+        self.chip
+            .display()
+            .pixels()
+            .map(|pixel| pixel as u8)
+            .collect()
+    }
+
+    pub fn is_done(&self) -> bool {
+        let timed_out = self
+            .config
+            .max_frames
+            .is_some_and(|frames| self.chip.frame_count() >= frames);
+        let finished = self
+            .config
+            .probes
+            .done
+            .is_some_and(|condition| condition.holds(&self.chip));
+
+        timed_out || finished
+    }
+
+    fn read_score(&self) -> u32 {
+        self.config
+            .probes
+            .reward
+            .map_or(0, |probe| probe.read(&self.chip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probes_must_fit_in_the_machines_ram() {
+        let probes = Probes::parse("reward = bcd:7FE:3").unwrap();
+        assert!(probes.check(&Chip8::new(None)).is_ok());
+
+        let small = Chip8::builder().ram_size(0x800).build().unwrap();
+        assert!(probes.check(&small).is_err());
+
+        assert!("bcd:FFFF:2".parse::<Probe>().is_err());
+    }
+
+    #[test]
+    fn probes_parse_hex_registers_and_bounded_bcd() {
+        assert_eq!("vE".parse(), Ok(Probe::Register(0xE)));
+        assert_eq!("VF".parse(), Ok(Probe::Register(0xF)));
+        assert_eq!("v0".parse(), Ok(Probe::Register(0)));
+        assert!("v10".parse::<Probe>().is_err());
+        assert!("vG".parse::<Probe>().is_err());
+
+        assert!("bcd:300:9".parse::<Probe>().is_ok());
+        assert!("bcd:300:10".parse::<Probe>().is_err());
+        assert!("bcd:300:0".parse::<Probe>().is_err());
+
+        // Bytes that aren't digits saturate
+        let chip = Chip8::builder().memory_fill(&[0xFF]).build().unwrap();
+        let probe: Probe = "bcd:300:9".parse().unwrap();
+        assert_eq!(probe.read(&chip), u32::MAX);
+    }
+
+    fn counting_env(probes: &str) -> Env {
+        // V0 += 1 in a loop, 5 times per frame at 10 instructions per frame
+        let config = EnvConfig {
+            ticks_per_frame: 10,
+            probes: Probes::parse(probes).unwrap(),
+            ..EnvConfig::default()
+        };
+        Env::new(&[0x70, 0x01, 0x12, 0x00], config).unwrap()
+    }
+
+    #[test]
+    fn steps_hold_the_action_for_frame_skip_frames() {
+        let mut env = counting_env("reward = v0");
+
+        let (observation, reward, done) = env.step(0b101);
+        assert_eq!(observation.len(), Env::OBSERVATION_SIZE);
+        assert_eq!(env.chip().frame_count(), 4);
+        assert_eq!(reward, 20.0);
+        assert!(!done);
+        assert_eq!(env.chip().keyboard()[..3], [true, false, true]);
+
+        let (_, reward, _) = env.step(0);
+        assert_eq!(reward, 20.0);
+        assert!(!env.chip().keyboard().contains(&true));
+    }
+
+    #[test]
+    fn steps_stop_early_once_the_episode_is_done() {
+        let mut env = counting_env("reward = v0\ndone = v0 > 30");
+
+        env.step(0);
+        let (_, reward, done) = env.step(0);
+        assert_eq!(env.chip().frame_count(), 7);
+        assert_eq!(reward, 15.0);
+        assert!(done);
+
+        let mut env = counting_env("");
+        env.config.max_frames = Some(6);
+        assert!(!env.step(0).2);
+        assert!(env.step(0).2);
+    }
+
+    #[test]
+    fn reset_boots_a_machine_seeded_with_the_given_seed() {
+        // Store a random byte at 0x300, then loop
+        let rom = [0xC0, 0xFF, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06];
+        let config = EnvConfig {
+            probes: Probes::parse("reward = ram:300").unwrap(),
+            ..EnvConfig::default()
+        };
+        let mut env = Env::new(&rom, config).unwrap();
+
+        let random_byte = |env: &mut Env, seed| {
+            env.reset(seed);
+            assert_eq!(env.chip().frame_count(), 0);
+            env.step(0).1
+        };
+        let first = random_byte(&mut env, 1);
+        assert_eq!(random_byte(&mut env, 1), first);
+        assert!((2..10).any(|seed| random_byte(&mut env, seed) != first));
+    }
+}
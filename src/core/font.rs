@@ -0,0 +1,315 @@
+//!
+//! Fonts for the hexadecimal digits drawn with `FX29` and, on SUPER-CHIP, the large
+//! decimal digits drawn with `FX30`. The original interpreters shaped their digits
+//! differently, so ROMs that print numbers only look right with their interpreter's font.
+//!
+//! Font files are raw binaries: the 16 small glyphs of 5 bytes each, optionally followed
+//! by large glyphs of 10 bytes each for the digits 0 to 9 or 0 to F.
+//!
+use std::{fmt, fs, str::FromStr};
+
+/// Bytes of a small font: 16 glyphs of 4x5 pixels, one byte per row
+pub const SMALL_FONT_SIZE: usize = 16 * SMALL_GLYPH_SIZE;
+pub const SMALL_GLYPH_SIZE: usize = 5;
+/// Bytes of a large glyph: 8x10 pixels, one byte per row
+pub const BIG_GLYPH_SIZE: usize = 10;
+
+const COSMAC_VIP: [u8; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const DREAM_6800: [u8; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const ETI_660: [u8; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const SUPER_CHIP: [u8; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// SUPER-CHIP 1.1 only has large glyphs for the decimal digits
+const SUPER_CHIP_BIG: [u8; 10 * BIG_GLYPH_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+
+///
+/// The built-in fonts, named after the interpreter they come from
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FontSet {
+    /// The original COSMAC VIP interpreter, used by default. Before font sets existed
+    /// machines drew the SUPER-CHIP shapes
+    #[default]
+    CosmacVip,
+    /// CHIPOS on the DREAM 6800, 3 pixels wide
+    Dream6800,
+    /// The ETI-660 interpreter, 3 pixels wide
+    Eti660,
+    /// SUPER-CHIP, including its large digits. Most modern interpreters use these shapes
+    SuperChip,
+}
+
+impl FontSet {
+    pub const ALL: [FontSet; 4] = [
+        FontSet::CosmacVip,
+        FontSet::Dream6800,
+        FontSet::Eti660,
+        FontSet::SuperChip,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FontSet::CosmacVip => "cosmac-vip",
+            FontSet::Dream6800 => "dream-6800",
+            FontSet::Eti660 => "eti-660",
+            FontSet::SuperChip => "super-chip",
+        }
+    }
+
+    pub fn font(&self) -> Font {
+        let (small, big): (&[u8; SMALL_FONT_SIZE], &[u8]) = match self {
+            FontSet::CosmacVip => (&COSMAC_VIP, &[]),
+            FontSet::Dream6800 => (&DREAM_6800, &[]),
+            FontSet::Eti660 => (&ETI_660, &[]),
+            FontSet::SuperChip => (&SUPER_CHIP, &SUPER_CHIP_BIG),
+        };
+
+        Font {
+            small: *small,
+            big: big.to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for FontSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for FontSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FontSet::ALL
+            .into_iter()
+            .find(|set| set.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = FontSet::ALL.iter().map(FontSet::name).collect();
+                format!(
+                    "unknown font '{}' (expected one of: {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+///
+/// Glyphs loaded into memory: the small font and the large one right after it
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Font {
+    small: [u8; SMALL_FONT_SIZE],
+    // Empty, or the large glyphs for 0 to 9 or 0 to F
+    big: Vec<u8>,
+}
+
+impl Font {
+    ///
+    /// Reads a font binary, see the module documentation for the layout
+    ///
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("failed to read font '{}': {}", path, e))?;
+        Font::from_bytes(&bytes).map_err(|error| format!("{}: {}", path, error))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < SMALL_FONT_SIZE {
+            return Err(format!(
+                "font has {} bytes, the small glyphs alone take {}",
+                bytes.len(),
+                SMALL_FONT_SIZE
+            ));
+        }
+
+        let (small, big) = bytes.split_at(SMALL_FONT_SIZE);
+        if ![0, 10 * BIG_GLYPH_SIZE, 16 * BIG_GLYPH_SIZE].contains(&big.len()) {
+            return Err(format!(
+                "{} bytes after the small glyphs are neither 10 nor 16 large glyphs",
+                big.len()
+            ));
+        }
+
+        Ok(Font {
+            small: small.try_into().unwrap(),
+            big: big.to_vec(),
+        })
+    }
+
+    pub fn small(&self) -> &[u8; SMALL_FONT_SIZE] {
+        &self.small
+    }
+
+    ///
+    /// The large glyphs, empty for fonts without them
+    ///
+    pub fn big(&self) -> &[u8] {
+        &self.big
+    }
+
+    ///
+    /// Bytes the font takes in memory
+    ///
+    pub fn size(&self) -> usize {
+        SMALL_FONT_SIZE + self.big.len()
+    }
+
+    ///
+    /// The font as laid out in memory and in font files
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [&self.small[..], &self.big].concat()
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        FontSet::default().font()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip8;
+
+    #[test]
+    fn op_fx29_and_fx30_respect_the_font_address() {
+        let mut chip = Chip8::builder()
+            .font_set(FontSet::SuperChip)
+            .font_address(0x050)
+            .build()
+            .unwrap();
+        assert_eq!(chip.memory()[..0x50], [0; 0x50]);
+
+        chip.set_v_register(0, 3).unwrap();
+        chip.execute(0xF029);
+        assert_eq!(chip.i_register(), 0x050 + 3 * 5);
+        assert_eq!(chip.memory()[0x05F..0x064], [0xF0, 0x10, 0xF0, 0x10, 0xF0]);
+
+        chip.execute(0xF030);
+        assert_eq!(chip.i_register(), 0x050 + 80 + 3 * 10);
+        assert_eq!(chip.memory()[0x0BE], 0x3C);
+        assert_eq!(
+            chip.memory()[0x0BE..0x0C8],
+            FontSet::SuperChip.font().big()[30..40]
+        );
+    }
+
+    #[test]
+    fn fonts_load_from_binaries() {
+        for set in FontSet::ALL {
+            let font = set.font();
+            assert_eq!(Font::from_bytes(&font.to_bytes()), Ok(font));
+            assert_eq!(set.name().parse(), Ok(set));
+        }
+
+        assert_eq!(Font::from_bytes(&[0; 240]).unwrap().big().len(), 160);
+        assert!(Font::from_bytes(&[0; 79]).is_err());
+        assert!(Font::from_bytes(&[0; 100]).is_err());
+        assert!("chip-8".parse::<FontSet>().is_err());
+    }
+
+    #[test]
+    fn op_fx30_needs_a_large_glyph_for_the_digit() {
+        // The default font has no large digits
+        let mut chip = Chip8::new(None);
+        assert_eq!(chip.font().size(), SMALL_FONT_SIZE);
+        chip.set_i_register(0x300);
+        chip.execute(0xF030);
+        assert_eq!(chip.i_register(), 0x300);
+
+        // SUPER-CHIP only has the decimal ones
+        let mut chip = Chip8::builder()
+            .font_set(FontSet::SuperChip)
+            .build()
+            .unwrap();
+        chip.set_i_register(0x300);
+        chip.set_v_register(0, 0xA).unwrap();
+        chip.execute(0xF030);
+        assert_eq!(chip.i_register(), 0x300);
+    }
+}
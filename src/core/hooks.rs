@@ -0,0 +1,23 @@
+use crate::Chip8;
+
+///
+/// Callbacks into tooling like scripts or debuggers, installed with `Chip8::set_hooks`.
+/// Every callback gets full access to the machine; hooks are not called recursively,
+/// e.g. when a callback runs instructions itself.
+///
+pub trait Hooks: Send + Sync {
+    ///
+    /// Called before each instruction is executed
+    ///
+    fn before_instruction(&mut self, _chip: &mut Chip8) {}
+
+    ///
+    /// Called after an instruction wrote a byte to RAM
+    ///
+    fn memory_written(&mut self, _chip: &mut Chip8, _address: u16, _value: u8) {}
+
+    ///
+    /// Called after each timer tick, i.e. at the end of every frame
+    ///
+    fn frame_end(&mut self, _chip: &mut Chip8) {}
+}
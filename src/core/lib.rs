@@ -7,210 +7,816 @@
 /// @mattmikolay @Timendus @aquova
 ///
 pub mod audio;
+pub mod batch;
+pub mod builder;
+pub mod cheats;
+mod decode;
+pub mod display;
+pub mod env;
+pub mod font;
+pub mod hooks;
+pub mod movie;
 pub mod opcodes;
+pub mod palette;
+pub mod quirks;
+pub mod recorder;
+pub mod rng;
+pub mod scheduler;
+pub mod screenshot;
+pub mod sound;
+pub mod state;
+pub mod timing;
 
-use audio::AudioBeep;
-use std::{fs::File, io::Read};
+use audio::Beeper;
+use builder::Chip8Builder;
+use decode::{DecodeCache, Decoded, Kind};
+use display::Display;
+use font::Font;
+use hooks::Hooks;
+use movie::{Input, InputEvent};
+use quirks::Quirks;
+use rng::{RandomSource, Rng};
+use state::SaveState;
+use std::{fs, io};
 
-const RAM_SIZE: usize = 4096;
+/// RAM of machines created with `Chip8::new`, see `Chip8Builder` for other sizes
+pub const RAM_SIZE: usize = 4096;
 const NUM_REGS: usize = 16;
-const STACK_SIZE: usize = 16;
-const START_ADDRESS: u16 = 0x200;
+/// Where `Chip8::new` machines load ROMs and start executing
+pub const START_ADDRESS: u16 = 0x200;
 
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
 
-const FONTSET_SIZE: usize = 80;
-pub const FONTSET: [u8; FONTSET_SIZE] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
+///
+/// Snapshot of the CPU registers and timers, see `Chip8::registers`
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub program_counter: u16,
+    pub i_register: u16,
+    pub v_registers: [u8; NUM_REGS],
+    /// Number of return addresses on the stack
+    pub stack_pointer: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
 
 pub struct Chip8 {
     program_counter: u16,
-    ram: [u8; RAM_SIZE],
+    ram: Box<[u8]>,
+    // Addresses wrap around at the end of RAM, like on the 4K COSMAC VIP
+    address_mask: usize,
+    start_address: u16,
+    // Loaded at `font_address` on every reset
+    font: Font,
+    font_address: u16,
+    // Repeated across RAM when the machine is powered on
+    memory_fill: Vec<u8>,
     v_registers: [u8; NUM_REGS],
     i_register: u16,
     stack_pointer: u16,
-    stack: [u16; STACK_SIZE],
+    stack: Box<[u16]>,
     delay_timer: u8,
+    sound_timer: u8,
     keyboard: [bool; 16],
-    audio: audio::AudioBeep,
+    audio: Option<Box<dyn Beeper>>,
+    beeping: bool,
+    rng: Rng,
+    rom: Vec<u8>,
+    frames: u64,
+    input_log: Option<Vec<InputEvent>>,
+    hooks: Option<Box<dyn Hooks>>,
+    // Addresses written by the current instruction, collected while hooks are installed
+    memory_writes: Option<Vec<u16>>,
+    display: Display,
+    decoded: DecodeCache,
 
-    pub sound_timer: u8,
-    pub screen: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    quirks: Quirks,
 }
 
 impl Chip8 {
-    pub fn new(audio: AudioBeep) -> Self {
-        let mut instance = Self {
-            program_counter: START_ADDRESS,
-            ram: [0; RAM_SIZE],
-            screen: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
-            v_registers: [0; NUM_REGS],
-            i_register: 0,
-            stack_pointer: 0,
-            stack: [0; STACK_SIZE],
-            delay_timer: 0,
-            sound_timer: 0,
-            keyboard: [false; 16],
-            audio,
-        };
+    ///
+    /// Creates a new machine with the default settings. Passing `None` for the audio runs
+    /// the machine silently, without opening an output device
+    ///
+    pub fn new(audio: Option<Box<dyn Beeper>>) -> Self {
+        let mut builder = Chip8Builder::new();
+        if let Some(audio) = audio {
+            builder = builder.audio(audio);
+        }
 
-        instance.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        builder.build().expect("the default settings are valid")
+    }
 
-        return instance;
+    ///
+    /// Starts configuring a machine with settings other than the defaults
+    ///
+    pub fn builder() -> Chip8Builder {
+        Chip8Builder::new()
     }
 
-    pub fn stack_push(&mut self, val: u16) {
+    ///
+    /// Pushes a return address. ROMs nesting more calls than the stack holds push the
+    /// oldest entry out instead of crashing the emulator
+    ///
+    pub(crate) fn stack_push(&mut self, val: u16) {
+        let depth = self.stack.len();
+        if self.stack_pointer as usize >= depth {
+            self.stack.copy_within(1.., 0);
+            self.stack_pointer = depth as u16 - 1;
+        }
+
         self.stack[self.stack_pointer as usize] = val;
         self.stack_pointer += 1;
     }
 
-    pub fn stack_pop(&mut self) -> u16 {
-        self.stack_pointer -= 1;
-        return self.stack[self.stack_pointer as usize];
+    ///
+    /// Pops a return address, `None` when the stack is empty
+    ///
+    pub(crate) fn stack_pop(&mut self) -> Option<u16> {
+        self.stack_pointer = self.stack_pointer.checked_sub(1)?;
+        Some(self.stack[self.stack_pointer as usize])
     }
 
     pub fn key_press(&mut self, index: usize, pressed: bool) {
         self.keyboard[index] = pressed;
+        self.log_input(Input::Key {
+            button: index as u8,
+            pressed,
+        });
     }
 
     ///
     /// Loads the provided ROM file into RAM starting at the start address
     ///
-    pub fn load_rom(&mut self, file_path: &str) {
-        println!("Loading ROM");
+    pub fn load_rom(&mut self, file_path: &str) -> io::Result<()> {
+        let rom = fs::read(file_path)?;
+        self.load_rom_bytes(&rom);
+        Ok(())
+    }
+
+    ///
+    /// Loads the provided ROM image into RAM starting at the start address.
+    /// Images larger than the space left in RAM are cut off
+    ///
+    pub fn load_rom_bytes(&mut self, rom: &[u8]) {
+        let start = self.start_address as usize;
+        let len = rom.len().min(self.ram.len() - start);
 
-        let mut rom = File::open(file_path).expect("Failed to open file");
-        let mut buffer = Vec::new();
+        self.ram[start..start + len].copy_from_slice(&rom[..len]);
+        self.decoded.clear();
+        self.rom = rom.to_vec();
+    }
 
-        rom.read_to_end(&mut buffer).expect("Failed to read file");
+    ///
+    /// FNV-1a hash of the loaded ROM image, identifying the ROM a movie was recorded with
+    ///
+    pub fn rom_hash(&self) -> u64 {
+        self.rom.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    }
+
+    ///
+    /// Restarts the random number generator used by `CXNN` from the given seed
+    ///
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Rng::with_source(self.rng.source(), seed);
+    }
+
+    pub fn rng_seed(&self) -> u64 {
+        self.rng.seed()
+    }
+
+    ///
+    /// Switches the algorithm used by `CXNN`, restarting it from the current seed
+    ///
+    pub fn set_random_source(&mut self, source: RandomSource) {
+        self.rng = Rng::with_source(source, self.rng.seed());
+    }
+
+    pub fn random_source(&self) -> RandomSource {
+        self.rng.source()
+    }
+
+    ///
+    /// Takes a snapshot of the machine that `load_state` can restore later
+    ///
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            program_counter: self.program_counter,
+            i_register: self.i_register,
+            stack_pointer: self.stack_pointer,
+            v_registers: self.v_registers,
+            stack: self.stack.to_vec(),
+            ram: self.ram.to_vec(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            keyboard: self.keyboard,
+            screen: self.display.pixels().collect(),
+            quirks: self.quirks,
+            rng: self.rng.clone(),
+            frames: self.frames,
+            beeping: self.beeping,
+        }
+    }
+
+    ///
+    /// Restores a snapshot taken with `save_state`. States that don't fit this
+    /// machine are rejected and leave it unchanged
+    ///
+    pub fn load_state(&mut self, state: &SaveState) -> Result<(), String> {
+        if state.ram.len() != self.ram.len()
+            || state.stack.len() != self.stack.len()
+            || state.screen.len() != SCREEN_WIDTH * SCREEN_HEIGHT
+        {
+            return Err("save state was made for a machine of a different size".to_string());
+        }
+        if state.stack_pointer as usize > self.stack.len() {
+            return Err("save state has an invalid stack pointer".to_string());
+        }
+
+        self.program_counter = state.program_counter;
+        self.i_register = state.i_register;
+        self.stack_pointer = state.stack_pointer;
+        self.v_registers = state.v_registers;
+        self.stack.copy_from_slice(&state.stack);
+        self.ram.copy_from_slice(&state.ram);
+        self.decoded.clear();
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.keyboard = state.keyboard;
+        self.display = Display::from_pixels(&state.screen);
+        self.quirks = state.quirks;
+        self.rng = state.rng.clone();
+        self.frames = state.frames;
+        self.beeping = state.beeping;
+
+        Ok(())
+    }
+
+    ///
+    /// Number of frames (timer ticks) run since the machine was created
+    ///
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    ///
+    /// Starts logging every key press and reset together with the frame it happened on
+    ///
+    pub fn start_input_recording(&mut self) {
+        self.input_log = Some(Vec::new());
+    }
 
-        let start = START_ADDRESS as usize;
-        let end = (START_ADDRESS as usize) + buffer.len();
+    ///
+    /// Stops logging inputs and returns the events logged so far
+    ///
+    pub fn stop_input_recording(&mut self) -> Vec<InputEvent> {
+        self.input_log.take().unwrap_or_default()
+    }
 
-        self.ram[start..end].copy_from_slice(&buffer);
+    fn log_input(&mut self, input: Input) {
+        let frame = self.frames;
+        if let Some(log) = &mut self.input_log {
+            log.push(InputEvent { frame, input });
+        }
     }
 
     ///
     /// Represents a single clock cycle of the Chip8
     ///
     pub fn tick(&mut self) {
-        let op = self.fetch_opcode();
-        self.execute(op);
+        let Some(mut hooks) = self.hooks.take() else {
+            self.step();
+            return;
+        };
+
+        hooks.before_instruction(self);
+
+        self.memory_writes = Some(Vec::new());
+        self.step();
+
+        for address in self.memory_writes.take().unwrap_or_default() {
+            let value = self.ram[address as usize];
+            hooks.memory_written(self, address, value);
+        }
+
+        self.hooks = Some(hooks);
+    }
+
+    ///
+    /// Executes `count` instructions, the same as calling `tick` that many times
+    ///
+    pub fn run_instructions(&mut self, count: u64) {
+        if self.hooks.is_some() {
+            for _ in 0..count {
+                self.tick();
+            }
+        } else {
+            for _ in 0..count {
+                self.step();
+            }
+        }
+    }
+
+    ///
+    /// Fetches and executes one instruction, decoding it only if it isn't cached yet
+    ///
+    fn step(&mut self) {
+        let pc = self.program_counter as usize & self.address_mask;
+        let mut decoded = self.decoded.get(pc);
+        if decoded.kind == Kind::Undecoded {
+            decoded = decode::decode(self.next_opcode());
+            self.decoded.insert(pc, decoded);
+        }
+
+        // PC is incremented by 2 after each 16 byte instruction
+        self.program_counter = self.program_counter.wrapping_add(2);
+        self.run(decoded);
+    }
+
+    ///
+    /// Installs callbacks run before every instruction, on memory writes and at the end
+    /// of every frame, replacing the previous ones
+    ///
+    pub fn set_hooks(&mut self, hooks: Option<Box<dyn Hooks>>) {
+        self.hooks = hooks;
+    }
+
+    ///
+    /// Reads a byte for an instruction. Addresses past the end of RAM wrap around
+    ///
+    pub(crate) fn read_ram(&self, address: usize) -> u8 {
+        self.ram[address & self.address_mask]
+    }
+
+    ///
+    /// Stores a byte written by an instruction, letting the hooks know about it.
+    /// Addresses past the end of RAM wrap around
+    ///
+    pub(crate) fn write_ram(&mut self, address: usize, value: u8) {
+        let address = address & self.address_mask;
+        self.ram[address] = value;
+        self.decoded.invalidate(address);
+
+        if let Some(writes) = &mut self.memory_writes {
+            writes.push(address as u16);
+        }
     }
 
     ///
     /// Decrements the delay and sound timers
+    ///
     pub fn tick_timers(&mut self) {
+        self.beeping = self.sound_timer > 0;
+        self.frames += 1;
+
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
 
         if self.sound_timer > 0 {
             if self.sound_timer == 1 {
-                self.audio.play();
+                if let Some(audio) = &self.audio {
+                    audio.play();
+                }
             }
 
             self.sound_timer -= 1;
-        } else if self.sound_timer == 0 {
-            self.audio.pause();
+        } else if let Some(audio) = &self.audio {
+            audio.pause();
+        }
+
+        if let Some(mut hooks) = self.hooks.take() {
+            hooks.frame_end(self);
+            self.hooks = Some(hooks);
+        }
+    }
+
+    ///
+    /// Whether the beeper sounded during the last frame, i.e. the sound timer was
+    /// non-zero at the last timer tick
+    ///
+    pub fn is_beeping(&self) -> bool {
+        self.beeping
+    }
+
+    ///
+    /// The framebuffer, read by frontends to draw the screen
+    ///
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    ///
+    /// Returns the bitmap of screen rows changed since the last call and marks them clean,
+    /// see `Display::take_dirty_rows`
+    ///
+    pub fn take_dirty_rows(&mut self) -> u32 {
+        self.display.take_dirty_rows()
+    }
+
+    ///
+    /// Snapshot of the CPU registers and timers
+    ///
+    pub fn registers(&self) -> Registers {
+        Registers {
+            program_counter: self.program_counter,
+            i_register: self.i_register,
+            v_registers: self.v_registers,
+            stack_pointer: self.stack_pointer,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
         }
     }
 
-    pub fn reset(&mut self) {
-        // Reset registers
-        self.program_counter = START_ADDRESS;
+    ///
+    /// The whole of RAM, including the font and the loaded ROM
+    ///
+    pub fn memory(&self) -> &[u8] {
+        &self.ram
+    }
+
+    ///
+    /// Where the small font starts, with the large font right after it
+    ///
+    pub fn font_address(&self) -> u16 {
+        self.font_address
+    }
+
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    ///
+    /// Where ROMs are loaded and execution starts after a reset
+    ///
+    pub fn start_address(&self) -> u16 {
+        self.start_address
+    }
+
+    ///
+    /// Number of return addresses the stack holds
+    ///
+    pub fn stack_depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    ///
+    /// The return addresses on the stack, oldest first. ROMs nesting calls deeper than
+    /// the stack lose the oldest ones, see `stack_push`
+    ///
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer as usize]
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    ///
+    /// Which of the 16 keypad buttons are held down
+    ///
+    pub fn keyboard(&self) -> &[bool; 16] {
+        &self.keyboard
+    }
+
+    pub fn v_registers(&self) -> &[u8; NUM_REGS] {
+        &self.v_registers
+    }
+
+    pub fn i_register(&self) -> u16 {
+        self.i_register
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    // Changing the machine from outside, for debuggers, scripts and tests. These changes
+    // aren't logged as inputs, so movies recorded while using them don't replay
+
+    ///
+    /// Overwrites every register and timer. The stack pointer must not exceed the stack size
+    ///
+    pub fn set_registers(&mut self, registers: &Registers) -> Result<(), String> {
+        if registers.stack_pointer as usize > self.stack.len() {
+            return Err(format!(
+                "stack pointer {} is larger than the stack",
+                registers.stack_pointer
+            ));
+        }
+
+        self.program_counter = registers.program_counter;
+        self.i_register = registers.i_register;
+        self.v_registers = registers.v_registers;
+        self.stack_pointer = registers.stack_pointer;
+        self.delay_timer = registers.delay_timer;
+        self.sound_timer = registers.sound_timer;
+        Ok(())
+    }
+
+    ///
+    /// Copies `bytes` into RAM starting at `address`
+    ///
+    pub fn write_memory(&mut self, address: u16, bytes: &[u8]) -> Result<(), String> {
+        let start = address as usize;
+        let end = start + bytes.len();
+        if end > self.ram.len() {
+            return Err(format!(
+                "writing {} bytes at {:#05x} goes past the end of RAM",
+                bytes.len(),
+                address
+            ));
+        }
+
+        self.ram[start..end].copy_from_slice(bytes);
+        for address in start..end {
+            self.decoded.invalidate(address);
+        }
+        Ok(())
+    }
+
+    ///
+    /// Replaces the stack with the given return addresses, oldest first
+    ///
+    pub fn set_stack(&mut self, addresses: &[u16]) -> Result<(), String> {
+        if addresses.len() > self.stack.len() {
+            return Err(format!(
+                "{} return addresses don't fit on a stack of {}",
+                addresses.len(),
+                self.stack.len()
+            ));
+        }
+
+        self.stack.fill(0);
+        self.stack[..addresses.len()].copy_from_slice(addresses);
+        self.stack_pointer = addresses.len() as u16;
+        Ok(())
+    }
+
+    pub fn set_v_register(&mut self, x: usize, value: u8) -> Result<(), String> {
+        let register = self
+            .v_registers
+            .get_mut(x)
+            .ok_or_else(|| format!("register {} is out of range", x))?;
+        *register = value;
+        Ok(())
+    }
+
+    ///
+    /// Switches to other quirks, e.g. when a ROM turns out to need a different platform
+    ///
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn set_i_register(&mut self, value: u16) {
+        self.i_register = value;
+    }
+
+    pub fn set_program_counter(&mut self, value: u16) {
+        self.program_counter = value;
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    ///
+    /// Restarts the program like a reset button: registers, stack, timers, keys and the
+    /// screen are cleared and the font and ROM image are loaded again, undoing any changes
+    /// the program made to itself. The rest of memory keeps its contents
+    ///
+    pub fn soft_reset(&mut self) {
+        self.log_input(Input::Reset);
+        self.restart();
+    }
+
+    ///
+    /// Power-cycles the machine: like `soft_reset`, but all of memory is filled with the
+    /// power-on pattern before the font and ROM are loaded, see `Chip8Builder::memory_fill`
+    ///
+    pub fn hard_reset(&mut self) {
+        self.log_input(Input::HardReset);
+        fill_memory(&mut self.ram, &self.memory_fill);
+        self.restart();
+    }
+
+    fn load_font(&mut self) {
+        let start = self.font_address as usize;
+        self.ram[start..start + self.font.size()].copy_from_slice(&self.font.to_bytes());
+    }
+
+    fn restart(&mut self) {
+        self.program_counter = self.start_address;
         self.v_registers = [0; NUM_REGS];
         self.i_register = 0;
         self.stack_pointer = 0;
-        self.stack = [0; STACK_SIZE];
-        self.ram = [0; RAM_SIZE];
+        self.stack.fill(0);
         self.delay_timer = 0;
         self.sound_timer = 0;
+        self.beeping = false;
         self.keyboard = [false; 16];
-        self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+        self.display.clear();
+
+        self.load_font();
+        let rom = std::mem::take(&mut self.rom);
+        self.load_rom_bytes(&rom);
     }
 
     ///
-    /// Fetches the next opcode from RAM and increments
-    /// the program counter by 2
+    /// Returns the opcode at the program counter without executing it
     ///
-    pub fn fetch_opcode(&mut self) -> u16 {
+    pub fn next_opcode(&self) -> u16 {
         let pc = self.program_counter as usize;
-        let high_byte = self.ram[pc] as u16;
-        let low_byte = self.ram[pc + 1] as u16;
-        let op = (high_byte << 8) | low_byte;
-
-        // PC is incremented by 2 after each 16 byte instruction
-        self.program_counter += 2;
+        let high_byte = self.read_ram(pc) as u16;
+        let low_byte = self.read_ram(pc + 1) as u16;
 
-        return op;
+        (high_byte << 8) | low_byte
     }
 
     ///
     /// Executes the provided opcode
     ///
     pub fn execute(&mut self, op: u16) {
-        // Extract the digits from the opcode
-        let digit1 = (op & 0xF000) >> 12;
+        self.run(decode::decode(op));
+    }
+
+    fn run(&mut self, decoded: Decoded) {
+        let op = decoded.op;
         let digit2 = (op & 0x0F00) >> 8;
         let digit3 = (op & 0x00F0) >> 4;
         let digit4 = op & 0x000F;
 
-        match (digit1, digit2, digit3, digit4) {
-            (0, 0, 0, 0) => return,
-            (0, 0, 0xE, 0) => opcodes::op_00e0(self, op),
-            (0, 0, 0xE, 0xE) => opcodes::op_00ee(self, op),
-            (1, _, _, _) => opcodes::op_1nnn(self, op),
-            (2, _, _, _) => opcodes::op_2nnn(self, op),
-            (3, _, _, _) => opcodes::op_3xnn(self, op, digit2),
-            (4, _, _, _) => opcodes::op_4xnn(self, op, digit2),
-            (5, _, _, 0) => opcodes::op_5xy0(self, digit2, digit3),
-            (6, _, _, _) => opcodes::op_6xnn(self, op, digit2),
-            (7, _, _, _) => opcodes::op_7xnn(self, op, digit2),
-            (8, _, _, 0) => opcodes::op_8xy0(self, digit2, digit3),
-            (8, _, _, 1) => opcodes::op_8xy1(self, digit2, digit3),
-            (8, _, _, 2) => opcodes::op_8xy2(self, digit2, digit3),
-            (8, _, _, 3) => opcodes::op_8xy3(self, digit2, digit3),
-            (8, _, _, 4) => opcodes::op_8xy4(self, digit2, digit3),
-            (8, _, _, 5) => opcodes::op_8xy5(self, digit2, digit3),
-            (8, _, _, 6) => opcodes::op_8xy6(self, digit2, digit3),
-            (8, _, _, 7) => opcodes::op_8xy7(self, digit2, digit3),
-            (8, _, _, 0xE) => opcodes::op_8xye(self, digit2, digit3),
-            (9, _, _, 0) => opcodes::op_9xy0(self, digit2, digit3),
-            (0xA, _, _, _) => opcodes::op_annn(self, op),
-            (0xC, _, _, _) => opcodes::op_cxnn(self, op, digit2),
-            (0xD, _, _, _) => opcodes::op_dxyn(self, digit2, digit3, digit4),
-            (0xE, _, 9, 0xE) => opcodes::op_ex9e(self, digit2),
-            (0xE, _, 0xA, 1) => opcodes::op_exa1(self, digit2),
-            (0xF, _, 0, 7) => opcodes::op_fx07(self, digit2),
-            (0xF, _, 0, 0xA) => opcodes::op_fx0a(self, digit2),
-            (0xF, _, 1, 5) => opcodes::op_fx15(self, digit2),
-            (0xF, _, 1, 8) => opcodes::op_fx18(self, digit2),
-            (0xF, _, 1, 0xE) => opcodes::op_fx1e(self, digit2),
-            (0xF, _, 2, 9) => opcodes::op_fx29(self, digit2),
-            (0xF, _, 3, 3) => opcodes::op_fx33(self, digit2),
-            (0xF, _, 5, 5) => opcodes::op_fx55(self, digit2),
-            (0xF, _, 6, 5) => opcodes::op_fx65(self, digit2),
-            (_, _, _, _) => {
-                println!("Unimplemented opcode: {:#04x}", op)
-            }
+        match decoded.kind {
+            Kind::Undecoded | Kind::Nop => (),
+            Kind::Op00e0 => opcodes::op_00e0(self, op),
+            Kind::Op00ee => opcodes::op_00ee(self, op),
+            Kind::Op1nnn => opcodes::op_1nnn(self, op),
+            Kind::Op2nnn => opcodes::op_2nnn(self, op),
+            Kind::Op3xnn => opcodes::op_3xnn(self, op, digit2),
+            Kind::Op4xnn => opcodes::op_4xnn(self, op, digit2),
+            Kind::Op5xy0 => opcodes::op_5xy0(self, digit2, digit3),
+            Kind::Op6xnn => opcodes::op_6xnn(self, op, digit2),
+            Kind::Op7xnn => opcodes::op_7xnn(self, op, digit2),
+            Kind::Op8xy0 => opcodes::op_8xy0(self, digit2, digit3),
+            Kind::Op8xy1 => opcodes::op_8xy1(self, digit2, digit3),
+            Kind::Op8xy2 => opcodes::op_8xy2(self, digit2, digit3),
+            Kind::Op8xy3 => opcodes::op_8xy3(self, digit2, digit3),
+            Kind::Op8xy4 => opcodes::op_8xy4(self, digit2, digit3),
+            Kind::Op8xy5 => opcodes::op_8xy5(self, digit2, digit3),
+            Kind::Op8xy6 => opcodes::op_8xy6(self, digit2, digit3),
+            Kind::Op8xy7 => opcodes::op_8xy7(self, digit2, digit3),
+            Kind::Op8xye => opcodes::op_8xye(self, digit2, digit3),
+            Kind::Op9xy0 => opcodes::op_9xy0(self, digit2, digit3),
+            Kind::Opannn => opcodes::op_annn(self, op),
+            Kind::Opbnnn => opcodes::op_bnnn(self, op),
+            Kind::Opcxnn => opcodes::op_cxnn(self, op, digit2),
+            Kind::Opdxyn => opcodes::op_dxyn(self, digit2, digit3, digit4),
+            Kind::Opex9e => opcodes::op_ex9e(self, digit2),
+            Kind::Opexa1 => opcodes::op_exa1(self, digit2),
+            Kind::Opfx07 => opcodes::op_fx07(self, digit2),
+            Kind::Opfx0a => opcodes::op_fx0a(self, digit2),
+            Kind::Opfx15 => opcodes::op_fx15(self, digit2),
+            Kind::Opfx18 => opcodes::op_fx18(self, digit2),
+            Kind::Opfx1e => opcodes::op_fx1e(self, digit2),
+            Kind::Opfx29 => opcodes::op_fx29(self, digit2),
+            Kind::Opfx30 => opcodes::op_fx30(self, digit2),
+            Kind::Opfx33 => opcodes::op_fx33(self, digit2),
+            Kind::Opfx55 => opcodes::op_fx55(self, digit2),
+            Kind::Opfx65 => opcodes::op_fx65(self, digit2),
+            // Skipped silently, ROMs running into data would otherwise flood the output
+            Kind::Unknown => (),
         }
     }
 }
+
+///
+/// Repeats `pattern` across all of `ram`, the way memory looks after powering on
+///
+fn fill_memory(ram: &mut [u8], pattern: &[u8]) {
+    for (byte, &value) in ram.iter_mut().zip(pattern.iter().cycle()) {
+        *byte = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_and_stack_can_be_inspected_and_changed() {
+        let mut chip = Chip8::new(None);
+        chip.set_program_counter(0x202);
+        chip.execute(0x2ABC);
+        assert_eq!(chip.stack(), [0x202]);
+        assert_eq!(chip.registers().stack_pointer, 1);
+
+        let registers = Registers {
+            program_counter: 0x300,
+            i_register: 0x123,
+            v_registers: [7; 16],
+            stack_pointer: 2,
+            delay_timer: 3,
+            sound_timer: 4,
+        };
+        chip.set_registers(&registers).unwrap();
+        assert_eq!(chip.registers(), registers);
+
+        chip.set_stack(&[0x204, 0x206]).unwrap();
+        chip.execute(0x00EE);
+        assert_eq!(chip.program_counter(), 0x206);
+        assert_eq!(chip.stack(), [0x204]);
+
+        assert!(chip.set_stack(&[0; 17]).is_err());
+        assert!(chip.set_v_register(16, 0).is_err());
+        assert!(chip
+            .set_registers(&Registers {
+                stack_pointer: 17,
+                ..registers
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn a_full_stack_is_visible_through_the_api() {
+        let mut chip = Chip8::new(None);
+        let calls: Vec<u16> = (0..16).map(|call| 0x200 + call * 2).collect();
+
+        for &address in &calls {
+            chip.set_program_counter(address);
+            chip.execute(0x2300);
+        }
+        assert_eq!(chip.stack(), calls);
+        assert_eq!(chip.registers().stack_pointer, 16);
+
+        // A full stack round-trips through the registers and save states
+        chip.set_registers(&chip.registers()).unwrap();
+        chip.load_state(&chip.save_state()).unwrap();
+        assert_eq!(chip.stack(), calls);
+
+        for _ in 0..16 {
+            chip.execute(0x00EE);
+        }
+        chip.execute(0x00EE);
+        assert!(chip.stack().is_empty());
+        assert_eq!(chip.program_counter(), 0x200);
+    }
+
+    #[test]
+    fn soft_reset_reloads_the_rom_and_font() {
+        let mut chip = Chip8::new(None);
+        chip.load_rom_bytes(&[0x60, 0x05, 0x12, 0x00]);
+        chip.write_memory(0x000, &[0xFF; 5]).unwrap();
+        chip.write_memory(0x201, &[0x09]).unwrap();
+        chip.write_memory(0x800, &[0x42]).unwrap();
+        chip.set_v_register(3, 7).unwrap();
+        chip.display = Display::from_pixels(&[true; SCREEN_WIDTH * SCREEN_HEIGHT]);
+
+        chip.soft_reset();
+        assert_eq!(
+            chip.registers(),
+            Registers {
+                program_counter: 0x200,
+                ..Registers::default()
+            }
+        );
+        assert_eq!(chip.memory()[..80], Font::default().small()[..]);
+        assert_eq!(chip.memory()[0x200..0x204], [0x60, 0x05, 0x12, 0x00]);
+        assert_eq!(chip.memory()[0x800], 0x42);
+        assert!(chip.display().pixels().all(|pixel| !pixel));
+
+        chip.run_instructions(1);
+        assert_eq!(chip.v_registers()[0], 5);
+    }
+
+    #[test]
+    fn hard_reset_fills_memory_with_the_power_on_pattern() {
+        let mut chip = Chip8::builder().memory_fill(&[0xAA, 0x55]).build().unwrap();
+        assert_eq!(chip.memory()[0x200..0x202], [0xAA, 0x55]);
+
+        chip.load_rom_bytes(&[0x60, 0x05]);
+        chip.write_memory(0x800, &[0x42]).unwrap();
+
+        chip.hard_reset();
+        assert_eq!(chip.memory()[..80], Font::default().small()[..]);
+        assert_eq!(chip.memory()[0x200..0x202], [0x60, 0x05]);
+        assert_eq!(chip.memory()[0x800..0x802], [0xAA, 0x55]);
+        assert_eq!(chip.program_counter(), 0x200);
+    }
+}
@@ -0,0 +1,348 @@
+//!
+//! Input movies: every key press of a session together with the frame it happened on,
+//! enough to replay the session exactly.
+//!
+//! Movies are stored as text, a header followed by one event per line:
+//!
+//! ```text
+//! chip8-movie 4
+//! rom 9f2c0d6e1a7b3c45
+//! seed 1234
+//! random splitmix64
+//! platform cosmac-vip
+//! timing fixed
+//! ips 600
+//! start 200
+//! font cosmac-vip
+//! font-address 0
+//! frames 3600
+//! 120 5 down
+//! 131 5 up
+//! 2000 reset
+//! 2600 hard-reset
+//! ```
+//!
+//! Version 1 movies have no `random` line and were recorded with SplitMix64. Before
+//! version 3, `reset` cleared all of memory, including the ROM, so older movies with
+//! resets can't be replayed. Movies before version 4 don't record the start address and
+//! font and replay with the defaults, programs starting at 0x200 and the default font at 0x000.
+//!
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    font::FontSet, quirks::Platform, rng::RandomSource, timing::TimingModel, Chip8, START_ADDRESS,
+};
+
+const MAGIC: &str = "chip8-movie";
+const VERSION: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    Key {
+        button: u8,
+        pressed: bool,
+    },
+    /// `Chip8::soft_reset`
+    Reset,
+    /// `Chip8::hard_reset`
+    HardReset,
+}
+
+///
+/// An input together with the number of frames completed before it happened
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub input: Input,
+}
+
+///
+/// A recorded session. Besides the inputs it holds everything that influences
+/// the outcome of a run, i.e. the ROM, the RNG seed and the emulation settings.
+/// Custom font files and cheats aren't part of a movie and can't be used with one.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub seed: u64,
+    pub random: RandomSource,
+    pub platform: Platform,
+    pub timing: TimingModel,
+    pub instructions_per_second: u32,
+    pub start_address: u16,
+    pub font: FontSet,
+    pub font_address: u16,
+    /// Length of the movie in frames
+    pub frames: u64,
+    pub events: Vec<InputEvent>,
+}
+
+impl Movie {
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{} {}", MAGIC, VERSION)?;
+        writeln!(writer, "rom {:016x}", self.rom_hash)?;
+        writeln!(writer, "seed {}", self.seed)?;
+        writeln!(writer, "random {}", self.random)?;
+        writeln!(writer, "platform {}", self.platform)?;
+        writeln!(writer, "timing {}", self.timing)?;
+        writeln!(writer, "ips {}", self.instructions_per_second)?;
+        writeln!(writer, "start {:X}", self.start_address)?;
+        writeln!(writer, "font {}", self.font)?;
+        writeln!(writer, "font-address {:X}", self.font_address)?;
+        writeln!(writer, "frames {}", self.frames)?;
+
+        for event in &self.events {
+            writeln!(writer, "{}", event)?;
+        }
+
+        writer.flush()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write(io::BufWriter::new(fs::File::create(path)?))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Movie> {
+        let contents = fs::read_to_string(path)?;
+        Movie::parse(&contents).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    pub fn parse(contents: &str) -> Result<Movie, String> {
+        let mut lines = contents
+            .lines()
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty());
+
+        let version = match lines.next().and_then(|(_, line)| line.split_once(' ')) {
+            Some((MAGIC, version)) => version
+                .parse::<u32>()
+                .ok()
+                .filter(|version| (1..=VERSION).contains(version))
+                .ok_or_else(|| format!("unsupported movie version '{}'", version))?,
+            _ => return Err("not a movie file".to_string()),
+        };
+
+        let mut header = |key: &str| -> Result<String, String> {
+            match lines.next() {
+                Some((_, line)) => match line.split_once(' ') {
+                    Some((name, value)) if name == key => Ok(value.to_string()),
+                    _ => Err(format!("expected '{}' in movie header", key)),
+                },
+                None => Err(format!("missing '{}' in movie header", key)),
+            }
+        };
+
+        let rom_hash = u64::from_str_radix(&header("rom")?, 16).map_err(|e| e.to_string())?;
+        let seed = header("seed")?
+            .parse()
+            .map_err(|e| format!("invalid seed: {}", e))?;
+        let random = match version {
+            1 => RandomSource::SplitMix,
+            _ => header("random")?.parse()?,
+        };
+        let platform = header("platform")?.parse()?;
+        let timing = header("timing")?.parse()?;
+        let instructions_per_second = header("ips")?
+            .parse()
+            .map_err(|e| format!("invalid ips: {}", e))?;
+        let (start_address, font, font_address) = match version {
+            1..=3 => (START_ADDRESS, FontSet::default(), 0),
+            _ => (
+                u16::from_str_radix(&header("start")?, 16)
+                    .map_err(|e| format!("invalid start address: {}", e))?,
+                header("font")?.parse()?,
+                u16::from_str_radix(&header("font-address")?, 16)
+                    .map_err(|e| format!("invalid font address: {}", e))?,
+            ),
+        };
+        let frames = header("frames")?
+            .parse()
+            .map_err(|e| format!("invalid frames: {}", e))?;
+
+        let events = lines
+            .map(|(number, line)| {
+                parse_event(line, version)
+                    .map_err(|error| format!("line {}: {}", number + 1, error))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Movie {
+            rom_hash,
+            seed,
+            random,
+            platform,
+            timing,
+            instructions_per_second,
+            start_address,
+            font,
+            font_address,
+            frames,
+            events,
+        })
+    }
+}
+
+fn parse_event(line: &str, version: u32) -> Result<InputEvent, String> {
+    let fields: Vec<_> = line.split_whitespace().collect();
+    let frame = fields[0]
+        .parse()
+        .map_err(|_| "invalid frame number".to_string())?;
+
+    let input =
+        match fields[1..] {
+            ["reset"] if version < 3 => {
+                return Err(format!(
+                    "resets of version {} movies cleared all memory and can't be replayed",
+                    version
+                ))
+            }
+            ["reset"] => Input::Reset,
+            ["hard-reset"] => Input::HardReset,
+            [button, state] => {
+                let button = u8::from_str_radix(button, 16)
+                    .ok()
+                    .filter(|button| *button < 16)
+                    .ok_or("button must be a hex digit between 0 and F")?;
+                let pressed = match state {
+                    "down" => true,
+                    "up" => false,
+                    _ => return Err("key state must be 'down' or 'up'".to_string()),
+                };
+
+                Input::Key { button, pressed }
+            }
+            _ => return Err(
+                "expected '<frame> <button> <down|up>', '<frame> reset' or '<frame> hard-reset'"
+                    .to_string(),
+            ),
+        };
+
+    Ok(InputEvent { frame, input })
+}
+
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.input {
+            Input::Key { button, pressed } => {
+                let state = if pressed { "down" } else { "up" };
+                write!(f, "{} {:X} {}", self.frame, button, state)
+            }
+            Input::Reset => write!(f, "{} reset", self.frame),
+            Input::HardReset => write!(f, "{} hard-reset", self.frame),
+        }
+    }
+}
+
+///
+/// Feeds the inputs of a movie back into a machine.
+///
+pub struct MoviePlayer {
+    movie: Movie,
+    next_event: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        MoviePlayer {
+            movie,
+            next_event: 0,
+        }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    ///
+    /// Applies the inputs that happened before the machine's current frame.
+    /// Call this in between frames, before running the next one.
+    ///
+    pub fn apply_inputs(&mut self, chip: &mut Chip8) {
+        while let Some(event) = self.movie.events.get(self.next_event) {
+            if event.frame > chip.frame_count() {
+                break;
+            }
+
+            match event.input {
+                Input::Key { button, pressed } => chip.key_press(button as usize, pressed),
+                Input::Reset => chip.soft_reset(),
+                Input::HardReset => chip.hard_reset(),
+            }
+            self.next_event += 1;
+        }
+    }
+
+    pub fn is_finished(&self, chip: &Chip8) -> bool {
+        chip.frame_count() >= self.movie.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1_MOVIE: &str = "chip8-movie 1
+        rom 9f2c0d6e1a7b3c45
+        seed 1234
+        platform cosmac-vip
+        timing fixed
+        ips 600
+        frames 3600
+        120 5 down";
+
+    #[test]
+    fn version_1_movies_use_splitmix() {
+        let movie = Movie::parse(V1_MOVIE).unwrap();
+        assert_eq!(movie.random, RandomSource::SplitMix);
+        assert_eq!(movie.seed, 1234);
+        assert_eq!(movie.events.len(), 1);
+    }
+
+    #[test]
+    fn movies_before_version_4_use_the_default_layout() {
+        let movie = Movie::parse(V1_MOVIE).unwrap();
+        assert_eq!(movie.start_address, START_ADDRESS);
+        assert_eq!(movie.font, FontSet::default());
+        assert_eq!(movie.font_address, 0x000);
+    }
+
+    #[test]
+    fn movies_round_trip_through_the_current_version() {
+        let mut movie = Movie::parse(V1_MOVIE).unwrap();
+        movie.random = RandomSource::CosmacVip;
+        movie.start_address = 0x600;
+        movie.font = FontSet::Eti660;
+        movie.font_address = 0x050;
+
+        let mut written = Vec::new();
+        movie.write(&mut written).unwrap();
+
+        assert!(written.starts_with(format!("{} {}\n", MAGIC, VERSION).as_bytes()));
+        assert_eq!(
+            Movie::parse(std::str::from_utf8(&written).unwrap()),
+            Ok(movie)
+        );
+    }
+
+    #[test]
+    fn resets_of_old_movies_are_rejected() {
+        let movie = format!("{}\n200 reset", V1_MOVIE);
+        assert!(Movie::parse(&movie).is_err());
+
+        let movie = movie.replacen("chip8-movie 1", "chip8-movie 3", 1);
+        let movie = movie.replacen("seed 1234", "seed 1234\nrandom splitmix64", 1);
+        assert_eq!(Movie::parse(&movie).unwrap().events[1].input, Input::Reset);
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        assert!(Movie::parse("chip8-movie 99\n").is_err());
+        assert!(Movie::parse("something else\n").is_err());
+    }
+}
@@ -1,9 +1,10 @@
-use crate::Chip8;
-use rand::random;
-
-///
-/// OP Code implementations for CHIP-8 emulator core.
-///
+//!
+//! OP Code implementations for CHIP-8 emulator core.
+//!
+use crate::{
+    font::{BIG_GLYPH_SIZE, SMALL_FONT_SIZE, SMALL_GLYPH_SIZE},
+    Chip8,
+};
 
 /// Used to mask the address from the opcode and extract the 12 least significant bits
 const ADDRESS_MASK: u16 = 0x0FFF;
@@ -16,14 +17,15 @@ const FLAG_REGISTER_INDEX: usize = 0xF;
 ///
 /// Clear screen.
 pub fn op_00e0(chip: &mut Chip8, _op: u16) {
-    chip.screen = [false; crate::SCREEN_WIDTH * crate::SCREEN_HEIGHT];
+    chip.display.clear();
 }
 
 ///
-/// Return from a subroutine
+/// Return from a subroutine. Returning with an empty stack does nothing
 pub fn op_00ee(chip: &mut Chip8, _op: u16) {
-    let return_address = chip.stack_pop();
-    chip.program_counter = return_address;
+    if let Some(return_address) = chip.stack_pop() {
+        chip.program_counter = return_address;
+    }
 }
 
 ///
@@ -43,35 +45,35 @@ pub fn op_2nnn(chip: &mut Chip8, op: u16) {
 }
 
 ///
-/// Skip next instruction if Vx != NN
+/// Skip next instruction if Vx == NN
 pub fn op_3xnn(chip: &mut Chip8, op: u16, digit2: u16) {
     let x = digit2 as usize;
     let nn = (op & VALUE_MASK) as u8;
 
     if chip.v_registers[x] == nn {
-        chip.program_counter += 2;
+        chip.program_counter = chip.program_counter.wrapping_add(2);
     }
 }
 
 ///
-/// Skip next instruction if Vx == NN
+/// Skip next instruction if Vx != NN
 pub fn op_4xnn(chip: &mut Chip8, op: u16, digit2: u16) {
     let x = digit2 as usize;
     let nn = (op & VALUE_MASK) as u8;
 
     if chip.v_registers[x] != nn {
-        chip.program_counter += 2;
+        chip.program_counter = chip.program_counter.wrapping_add(2);
     }
 }
 
 ///
-/// Skip next instruction if Vx != Vy
+/// Skip next instruction if Vx == Vy
 pub fn op_5xy0(chip: &mut Chip8, digit2: u16, digit3: u16) {
     let x = digit2 as usize;
     let y = digit3 as usize;
 
     if chip.v_registers[x] == chip.v_registers[y] {
-        chip.program_counter += 2;
+        chip.program_counter = chip.program_counter.wrapping_add(2);
     }
 }
 
@@ -109,6 +111,10 @@ pub fn op_8xy1(chip: &mut Chip8, digit2: u16, digit3: u16) {
     let y = digit3 as usize;
 
     chip.v_registers[x] |= chip.v_registers[y];
+
+    if chip.quirks.vf_reset {
+        chip.v_registers[FLAG_REGISTER_INDEX] = 0;
+    }
 }
 
 ///
@@ -118,6 +124,10 @@ pub fn op_8xy2(chip: &mut Chip8, digit2: u16, digit3: u16) {
     let y = digit3 as usize;
 
     chip.v_registers[x] &= chip.v_registers[y];
+
+    if chip.quirks.vf_reset {
+        chip.v_registers[FLAG_REGISTER_INDEX] = 0;
+    }
 }
 
 ///
@@ -127,6 +137,10 @@ pub fn op_8xy3(chip: &mut Chip8, digit2: u16, digit3: u16) {
     let y = digit3 as usize;
 
     chip.v_registers[x] ^= chip.v_registers[y];
+
+    if chip.quirks.vf_reset {
+        chip.v_registers[FLAG_REGISTER_INDEX] = 0;
+    }
 }
 
 ///
@@ -156,15 +170,15 @@ pub fn op_8xy5(chip: &mut Chip8, digit2: u16, digit3: u16) {
 }
 
 ///
-/// Set Vx = Vx SHR 1
+/// Set Vx = Vx SHR 1, set VF = the bit shifted out
 pub fn op_8xy6(chip: &mut Chip8, digit2: u16, digit3: u16) {
     let x = digit2 as usize;
     let y = digit3 as usize;
 
-    let vy_value = chip.v_registers[y];
-    let least_bit = vy_value & 1;
+    let value = shift_source(chip, x, y);
+    let least_bit = value & 1;
 
-    chip.v_registers[x] = (vy_value >> 1) as u8;
+    chip.v_registers[x] = value >> 1;
     chip.v_registers[FLAG_REGISTER_INDEX] = least_bit;
 }
 
@@ -182,16 +196,26 @@ pub fn op_8xy7(chip: &mut Chip8, digit2: u16, digit3: u16) {
 }
 
 ///
-/// Set Vx = Vx SHL 1
+/// Set Vx = Vx SHL 1, set VF = the bit shifted out
 pub fn op_8xye(chip: &mut Chip8, digit2: u16, digit3: u16) {
     let x = digit2 as usize;
     let y = digit3 as usize;
 
-    let vy_value = chip.v_registers[y];
-    let least_bit = vy_value & 1;
+    let value = shift_source(chip, x, y);
+    let most_bit = value >> 7;
 
-    chip.v_registers[x] = (vy_value << 1) as u8;
-    chip.v_registers[FLAG_REGISTER_INDEX] = least_bit;
+    chip.v_registers[x] = value << 1;
+    chip.v_registers[FLAG_REGISTER_INDEX] = most_bit;
+}
+
+///
+/// The original interpreter shifts Vy into Vx, while later ones shift Vx in place
+fn shift_source(chip: &Chip8, x: usize, y: usize) -> u8 {
+    if chip.quirks.shift_uses_vy {
+        chip.v_registers[y]
+    } else {
+        chip.v_registers[x]
+    }
 }
 
 ///
@@ -201,7 +225,7 @@ pub fn op_9xy0(chip: &mut Chip8, digit2: u16, digit3: u16) {
     let y = digit3 as usize;
 
     if chip.v_registers[x] != chip.v_registers[y] {
-        chip.program_counter += 2;
+        chip.program_counter = chip.program_counter.wrapping_add(2);
     }
 }
 
@@ -213,11 +237,18 @@ pub fn op_annn(chip: &mut Chip8, op: u16) {
 }
 
 ///
-/// Jump to location NNN + V0
+/// Jump to NNN + V0
+pub fn op_bnnn(chip: &mut Chip8, op: u16) {
+    let nnn = op & ADDRESS_MASK;
+    chip.program_counter = nnn + chip.v_registers[0] as u16;
+}
+
+///
+/// Set Vx = random byte AND NN
 pub fn op_cxnn(chip: &mut Chip8, op: u16, digit2: u16) {
     let x = digit2 as usize;
     let nn = (op & VALUE_MASK) as u8;
-    let random_value: u8 = random();
+    let random_value = chip.rng.next_u8(&chip.ram);
 
     chip.v_registers[x] = random_value & nn;
 }
@@ -225,41 +256,22 @@ pub fn op_cxnn(chip: &mut Chip8, op: u16, digit2: u16) {
 ///
 /// Draw a sprite at position (Vx, Vy) with N bytes of sprite data starting at the address stored in I
 pub fn op_dxyn(chip: &mut Chip8, digit2: u16, digit3: u16, digit4: u16) {
-    // Get the (x, y) coords for our sprite
-    let x_coord = chip.v_registers[digit2 as usize] as u16;
-    let y_coord = chip.v_registers[digit3 as usize] as u16;
+    let x = chip.v_registers[digit2 as usize] as usize;
+    let y = chip.v_registers[digit3 as usize] as usize;
     // The last digit determines how many rows high our sprite is
-    let num_rows = digit4;
-
-    // Keep track if any pixels were flipped
-    let mut flipped = false;
-    // Iterate over each row of our sprite
-    for y_line in 0..num_rows {
-        // Determine which memory address our row's data is stored
-        let addr = chip.i_register + y_line as u16;
-        let pixels = chip.ram[addr as usize];
-        // Iterate over each column in our row
-        for x_line in 0..8 {
-            // Use a mask to fetch current pixel's bit. Only flip if a 1
-            if (pixels & (0b1000_0000 >> x_line)) != 0 {
-                // Sprites should wrap around screen, so apply modulo
-                let x = (x_coord + x_line) as usize % crate::SCREEN_WIDTH;
-                let y = (y_coord + y_line) as usize % crate::SCREEN_HEIGHT;
-
-                // Get our pixel's index in the 1D screen array
-                let idx = x + crate::SCREEN_WIDTH * y;
-                // Check if we're about to flip the pixel and set
-                flipped |= chip.screen[idx];
-                chip.screen[idx] ^= true;
-            }
-        }
+    let mut sprite = [0; 15];
+    let sprite = &mut sprite[..digit4 as usize];
+    for (row, byte) in sprite.iter_mut().enumerate() {
+        *byte = chip.read_ram(chip.i_register as usize + row);
     }
+
+    // Sprites are either clipped at the edges or wrap around the screen
+    let flipped = chip
+        .display
+        .draw_sprite(x, y, sprite, chip.quirks.clip_sprites);
+
     // Populate VF register
-    if flipped {
-        chip.v_registers[0xF] = 1;
-    } else {
-        chip.v_registers[0xF] = 0;
-    }
+    chip.v_registers[FLAG_REGISTER_INDEX] = flipped as u8;
 }
 
 ///
@@ -267,10 +279,11 @@ pub fn op_dxyn(chip: &mut Chip8, digit2: u16, digit3: u16, digit4: u16) {
 pub fn op_ex9e(chip: &mut Chip8, digit2: u16) {
     let x = digit2 as usize;
     let vx = chip.v_registers[x];
-    let key = chip.keyboard[vx as usize];
+    // Only the low nibble selects a key
+    let key = chip.keyboard[(vx & 0xF) as usize];
 
     if key {
-        chip.program_counter += 2;
+        chip.program_counter = chip.program_counter.wrapping_add(2);
     }
 }
 
@@ -279,15 +292,16 @@ pub fn op_ex9e(chip: &mut Chip8, digit2: u16) {
 pub fn op_exa1(chip: &mut Chip8, digit2: u16) {
     let x = digit2 as usize;
     let vx = chip.v_registers[x];
-    let key = chip.keyboard[vx as usize];
+    // Only the low nibble selects a key
+    let key = chip.keyboard[(vx & 0xF) as usize];
 
     if !key {
-        chip.program_counter += 2;
+        chip.program_counter = chip.program_counter.wrapping_add(2);
     }
 }
 
 ///
-/// Set Vx = delay timer value
+/// Wait for a key press and store the key in Vx
 pub fn op_fx0a(chip: &mut Chip8, digit2: u16) {
     let x = digit2 as usize;
     let mut key_pressed = false;
@@ -301,28 +315,46 @@ pub fn op_fx0a(chip: &mut Chip8, digit2: u16) {
     }
 
     if !key_pressed {
-        chip.program_counter -= 2;
+        chip.program_counter = chip.program_counter.wrapping_sub(2);
     }
 }
 
 ///
-/// Set I = location of sprite for digit Vx
+/// Set sound timer = Vx
 pub fn op_fx18(chip: &mut Chip8, digit2: u16) {
     let x = digit2 as usize;
     chip.sound_timer = chip.v_registers[x];
 }
 
 ///
-/// Set I = location of sprite for digit Vx
+/// Set I = location of sprite for digit Vx. Only the low nibble of Vx selects the digit
 pub fn op_fx29(chip: &mut Chip8, digit2: u16) {
     let x = digit2 as usize;
-    let c = chip.v_registers[x] as u16;
+    let c = (chip.v_registers[x] & 0xF) as u16;
 
-    chip.i_register = c * 5;
+    chip.i_register = chip.font_address + c * SMALL_GLYPH_SIZE as u16;
 }
 
 ///
+/// Set I = location of the large sprite for digit Vx. The large glyphs follow the small
+/// ones in memory; of the built-in fonts only SUPER-CHIP has them. Digits the font has no
+/// large glyph for leave I unchanged. It isn't gated by platform: with a font without large
+/// digits it does nothing, as on interpreters that don't know it, and a font with them was
+/// picked for ROMs that use it
 ///
+pub fn op_fx30(chip: &mut Chip8, digit2: u16) {
+    let x = digit2 as usize;
+    let c = (chip.v_registers[x] & 0xF) as usize;
+    if c >= chip.font.big().len() / BIG_GLYPH_SIZE {
+        return;
+    }
+
+    let address = chip.font_address as usize + SMALL_FONT_SIZE + c * BIG_GLYPH_SIZE;
+    chip.i_register = (address & chip.address_mask) as u16;
+}
+
+///
+/// Set I = I + Vx
 pub fn op_fx1e(chip: &mut Chip8, digit2: u16) {
     let x = digit2 as usize;
     let vx = chip.v_registers[x] as u16;
@@ -344,37 +376,700 @@ pub fn op_fx15(chip: &mut Chip8, digit2: u16) {
     chip.delay_timer = chip.v_registers[x];
 }
 
+///
+/// Store the decimal digits of Vx at I, I + 1 and I + 2
 pub fn op_fx33(chip: &mut Chip8, digit2: u16) {
     let x = digit2 as usize;
-    let mut vx = chip.v_registers[x] as f32;
-
-    let ones = vx % 10.0;
-    vx /= 10.0;
-
-    let tens = vx % 10.0;
-    vx /= 10.0;
-
-    let hundreds = vx % 10.0;
+    let vx = chip.v_registers[x];
 
-    chip.ram[chip.i_register as usize] = hundreds as u8;
-    chip.ram[(chip.i_register + 1) as usize] = tens as u8;
-    chip.ram[(chip.i_register + 2) as usize] = ones as u8;
+    chip.write_ram(chip.i_register as usize, vx / 100);
+    chip.write_ram(chip.i_register as usize + 1, vx / 10 % 10);
+    chip.write_ram(chip.i_register as usize + 2, vx % 10);
 }
 
+///
+/// Store V0 to Vx in memory starting at I
 pub fn op_fx55(chip: &mut Chip8, digit2: u16) {
     let x = digit2 as usize;
     let i = chip.i_register as usize;
 
     for idx in 0..=x {
-        chip.ram[i + idx] = chip.v_registers[idx];
+        chip.write_ram(i + idx, chip.v_registers[idx]);
+    }
+
+    if chip.quirks.memory_increment {
+        chip.i_register = chip.i_register.wrapping_add(x as u16 + 1);
     }
 }
 
+///
+/// Load V0 to Vx from memory starting at I
 pub fn op_fx65(chip: &mut Chip8, digit2: u16) {
     let x = digit2 as usize;
     let i = chip.i_register as usize;
 
     for idx in 0..=x {
-        chip.v_registers[idx] = chip.ram[i + idx];
+        chip.v_registers[idx] = chip.read_ram(i + idx);
+    }
+
+    if chip.quirks.memory_increment {
+        chip.i_register = chip.i_register.wrapping_add(x as u16 + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{display::Display, font::Font, quirks::Quirks, Chip8, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    const NO_QUIRKS: Quirks = Quirks {
+        vf_reset: false,
+        memory_increment: false,
+        shift_uses_vy: false,
+        clip_sprites: false,
+    };
+
+    /// A silent machine positioned as if the instruction at 0x200 had just been fetched
+    fn machine(quirks: Quirks) -> Chip8 {
+        let mut chip = Chip8::new(None);
+        chip.quirks = quirks;
+        chip.program_counter = 0x202;
+        chip
+    }
+
+    fn pixel(chip: &Chip8, x: usize, y: usize) -> bool {
+        chip.display.pixel(x, y)
+    }
+
+    #[test]
+    fn op_00e0_clears_the_screen() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.display = Display::from_pixels(&[true; SCREEN_WIDTH * SCREEN_HEIGHT]);
+
+        chip.execute(0x00E0);
+
+        assert!(chip.display.pixels().all(|pixel| !pixel));
+    }
+
+    #[test]
+    fn op_2nnn_and_00ee_call_and_return() {
+        let mut chip = machine(NO_QUIRKS);
+
+        chip.execute(0x2ABC);
+        assert_eq!(chip.program_counter, 0xABC);
+        assert_eq!(chip.stack_pointer, 1);
+        assert_eq!(chip.stack[0], 0x202);
+
+        chip.execute(0x00EE);
+        assert_eq!(chip.program_counter, 0x202);
+        assert_eq!(chip.stack_pointer, 0);
+    }
+
+    #[test]
+    fn op_1nnn_jumps() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.execute(0x1345);
+        assert_eq!(chip.program_counter, 0x345);
+    }
+
+    #[test]
+    fn op_3xnn_skips_if_equal() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[3] = 0x42;
+
+        chip.execute(0x3342);
+        assert_eq!(chip.program_counter, 0x204);
+
+        chip.execute(0x3343);
+        assert_eq!(chip.program_counter, 0x204);
+    }
+
+    #[test]
+    fn op_4xnn_skips_if_not_equal() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[3] = 0x42;
+
+        chip.execute(0x4342);
+        assert_eq!(chip.program_counter, 0x202);
+
+        chip.execute(0x4343);
+        assert_eq!(chip.program_counter, 0x204);
+    }
+
+    #[test]
+    fn op_5xy0_skips_if_registers_equal() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[1] = 7;
+        chip.v_registers[2] = 7;
+        chip.v_registers[3] = 8;
+
+        chip.execute(0x5120);
+        assert_eq!(chip.program_counter, 0x204);
+
+        chip.execute(0x5130);
+        assert_eq!(chip.program_counter, 0x204);
+    }
+
+    #[test]
+    fn op_9xy0_skips_if_registers_differ() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[1] = 7;
+        chip.v_registers[2] = 7;
+        chip.v_registers[3] = 8;
+
+        chip.execute(0x9120);
+        assert_eq!(chip.program_counter, 0x202);
+
+        chip.execute(0x9130);
+        assert_eq!(chip.program_counter, 0x204);
+    }
+
+    #[test]
+    fn op_6xnn_loads() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.execute(0x6A5F);
+        assert_eq!(chip.v_registers[0xA], 0x5F);
+    }
+
+    #[test]
+    fn op_7xnn_wraps_without_touching_vf() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[0] = 0xFF;
+        chip.v_registers[0xF] = 0x55;
+
+        chip.execute(0x7002);
+
+        assert_eq!(chip.v_registers[0], 0x01);
+        assert_eq!(chip.v_registers[0xF], 0x55);
+    }
+
+    #[test]
+    fn op_8xy0_copies() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[2] = 0x99;
+        chip.execute(0x8120);
+        assert_eq!(chip.v_registers[1], 0x99);
+    }
+
+    #[test]
+    fn op_8xy1_to_8xy3_compute_logic() {
+        for (op, expected) in [(0x8011, 0b1110), (0x8012, 0b1000), (0x8013, 0b0110)] {
+            let mut chip = machine(NO_QUIRKS);
+            chip.v_registers[0] = 0b1100;
+            chip.v_registers[1] = 0b1010;
+            chip.v_registers[0xF] = 0x55;
+
+            chip.execute(op);
+
+            assert_eq!(chip.v_registers[0], expected, "{:04X}", op);
+            assert_eq!(chip.v_registers[0xF], 0x55, "{:04X}", op);
+        }
+    }
+
+    #[test]
+    fn op_8xy1_to_8xy3_reset_vf_with_quirk() {
+        let quirks = Quirks {
+            vf_reset: true,
+            ..NO_QUIRKS
+        };
+        for op in [0x8011, 0x8012, 0x8013] {
+            let mut chip = machine(quirks);
+            chip.v_registers[0] = 0b1100;
+            chip.v_registers[1] = 0b1010;
+            chip.v_registers[0xF] = 0x55;
+
+            chip.execute(op);
+
+            assert_eq!(chip.v_registers[0xF], 0, "{:04X}", op);
+        }
+
+        // The reset wins over a result stored in VF
+        let mut chip = machine(quirks);
+        chip.v_registers[0xF] = 0x0F;
+        chip.v_registers[1] = 0xF0;
+        chip.execute(0x8F11);
+        assert_eq!(chip.v_registers[0xF], 0);
+    }
+
+    #[test]
+    fn op_8xy4_adds_with_carry() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[0] = 0x10;
+        chip.v_registers[1] = 0x20;
+        chip.execute(0x8014);
+        assert_eq!(chip.v_registers[0], 0x30);
+        assert_eq!(chip.v_registers[0xF], 0);
+
+        chip.v_registers[0] = 0xFF;
+        chip.v_registers[1] = 0x02;
+        chip.execute(0x8014);
+        assert_eq!(chip.v_registers[0], 0x01);
+        assert_eq!(chip.v_registers[0xF], 1);
+    }
+
+    #[test]
+    fn op_8xy4_with_vf_operands() {
+        // VF as Vx: the flag overwrites the sum
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[0xF] = 0xFF;
+        chip.v_registers[1] = 0x02;
+        chip.execute(0x8F14);
+        assert_eq!(chip.v_registers[0xF], 1);
+
+        // VF as Vy: the sum uses VF's value from before the instruction
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[0] = 0x01;
+        chip.v_registers[0xF] = 0x01;
+        chip.execute(0x80F4);
+        assert_eq!(chip.v_registers[0], 0x02);
+        assert_eq!(chip.v_registers[0xF], 0);
+    }
+
+    #[test]
+    fn op_8xy5_subtracts_with_not_borrow() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[0] = 0x30;
+        chip.v_registers[1] = 0x10;
+        chip.execute(0x8015);
+        assert_eq!(chip.v_registers[0], 0x20);
+        assert_eq!(chip.v_registers[0xF], 1);
+
+        chip.v_registers[0] = 0x10;
+        chip.v_registers[1] = 0x10;
+        chip.execute(0x8015);
+        assert_eq!(chip.v_registers[0], 0x00);
+        assert_eq!(chip.v_registers[0xF], 1);
+
+        chip.v_registers[0] = 0x10;
+        chip.v_registers[1] = 0x11;
+        chip.execute(0x8015);
+        assert_eq!(chip.v_registers[0], 0xFF);
+        assert_eq!(chip.v_registers[0xF], 0);
+    }
+
+    #[test]
+    fn op_8xy5_with_vf_as_vx() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[0xF] = 0x05;
+        chip.v_registers[1] = 0x01;
+        chip.execute(0x8F15);
+        assert_eq!(chip.v_registers[0xF], 1);
+    }
+
+    #[test]
+    fn op_8xy7_subtracts_reversed() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[0] = 0x10;
+        chip.v_registers[1] = 0x30;
+        chip.execute(0x8017);
+        assert_eq!(chip.v_registers[0], 0x20);
+        assert_eq!(chip.v_registers[0xF], 1);
+
+        chip.v_registers[0] = 0x31;
+        chip.v_registers[1] = 0x30;
+        chip.execute(0x8017);
+        assert_eq!(chip.v_registers[0], 0xFF);
+        assert_eq!(chip.v_registers[0xF], 0);
+    }
+
+    #[test]
+    fn op_8xy6_shifts_right() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[0] = 0b0000_0101;
+        chip.v_registers[1] = 0b1000_0000;
+        chip.execute(0x8016);
+        assert_eq!(chip.v_registers[0], 0b0000_0010);
+        assert_eq!(chip.v_registers[0xF], 1);
+
+        chip.execute(0x8016);
+        assert_eq!(chip.v_registers[0], 0b0000_0001);
+        assert_eq!(chip.v_registers[0xF], 0);
+    }
+
+    #[test]
+    fn op_8xy6_shifts_vy_with_quirk() {
+        let mut chip = machine(Quirks {
+            shift_uses_vy: true,
+            ..NO_QUIRKS
+        });
+        chip.v_registers[0] = 0b0000_0101;
+        chip.v_registers[1] = 0b1000_0000;
+        chip.execute(0x8016);
+        assert_eq!(chip.v_registers[0], 0b0100_0000);
+        assert_eq!(chip.v_registers[1], 0b1000_0000);
+        assert_eq!(chip.v_registers[0xF], 0);
+    }
+
+    #[test]
+    fn op_8xye_shifts_left_into_vf() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[0] = 0b1000_0010;
+        chip.execute(0x801E);
+        assert_eq!(chip.v_registers[0], 0b0000_0100);
+        assert_eq!(chip.v_registers[0xF], 1);
+
+        chip.v_registers[0] = 0b0100_0001;
+        chip.execute(0x801E);
+        assert_eq!(chip.v_registers[0], 0b1000_0010);
+        assert_eq!(chip.v_registers[0xF], 0);
+    }
+
+    #[test]
+    fn op_8xye_shifts_vy_with_quirk() {
+        let mut chip = machine(Quirks {
+            shift_uses_vy: true,
+            ..NO_QUIRKS
+        });
+        chip.v_registers[0] = 0b0000_0001;
+        chip.v_registers[1] = 0b1100_0000;
+        chip.execute(0x801E);
+        assert_eq!(chip.v_registers[0], 0b1000_0000);
+        assert_eq!(chip.v_registers[0xF], 1);
+    }
+
+    #[test]
+    fn shifts_with_vf_as_vx_keep_the_flag() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[0xF] = 0b1000_0000;
+        chip.execute(0x8F0E);
+        assert_eq!(chip.v_registers[0xF], 1);
+
+        chip.v_registers[0xF] = 0b0000_0010;
+        chip.execute(0x8F06);
+        assert_eq!(chip.v_registers[0xF], 0);
+    }
+
+    #[test]
+    fn op_annn_sets_i() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.execute(0xA123);
+        assert_eq!(chip.i_register, 0x123);
+    }
+
+    #[test]
+    fn op_bnnn_jumps_with_offset() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[0] = 0x10;
+        chip.v_registers[3] = 0x20;
+        chip.execute(0xB300);
+        assert_eq!(chip.program_counter, 0x310);
+    }
+
+    #[test]
+    fn op_cxnn_masks_the_random_byte() {
+        let mut chip = machine(NO_QUIRKS);
+        for _ in 0..64 {
+            chip.execute(0xC00F);
+            assert_eq!(chip.v_registers[0] & 0xF0, 0);
+        }
+
+        chip.execute(0xC000);
+        assert_eq!(chip.v_registers[0], 0);
+    }
+
+    #[test]
+    fn op_cxnn_is_reproducible_from_the_seed() {
+        let values = |seed| {
+            let mut chip = machine(NO_QUIRKS);
+            chip.seed_rng(seed);
+            (0..16)
+                .map(|_| {
+                    chip.execute(0xC0FF);
+                    chip.v_registers[0]
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(values(1), values(1));
+        assert_ne!(values(1), values(2));
+    }
+
+    #[test]
+    fn op_dxyn_draws_and_detects_collisions() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.ram[0x300] = 0b1100_0000;
+        chip.i_register = 0x300;
+        chip.v_registers[0] = 10;
+        chip.v_registers[1] = 5;
+
+        chip.execute(0xD011);
+        assert!(pixel(&chip, 10, 5) && pixel(&chip, 11, 5));
+        assert!(!pixel(&chip, 12, 5));
+        assert_eq!(chip.v_registers[0xF], 0);
+
+        // Drawing the same sprite again erases it and reports the collision
+        chip.execute(0xD011);
+        assert!(!pixel(&chip, 10, 5) && !pixel(&chip, 11, 5));
+        assert_eq!(chip.v_registers[0xF], 1);
+    }
+
+    #[test]
+    fn op_dxyn_wraps_the_start_position() {
+        let mut chip = machine(Quirks {
+            clip_sprites: true,
+            ..NO_QUIRKS
+        });
+        chip.ram[0x300] = 0b1000_0000;
+        chip.i_register = 0x300;
+        chip.v_registers[0] = 64 + 3;
+        chip.v_registers[1] = 32 + 2;
+
+        chip.execute(0xD011);
+
+        assert!(pixel(&chip, 3, 2));
+    }
+
+    #[test]
+    fn op_dxyn_clips_or_wraps_at_the_edges() {
+        for clip_sprites in [true, false] {
+            let mut chip = machine(Quirks {
+                clip_sprites,
+                ..NO_QUIRKS
+            });
+            chip.ram[0x300] = 0b1100_0000;
+            chip.ram[0x301] = 0b1100_0000;
+            chip.i_register = 0x300;
+            chip.v_registers[0] = 63;
+            chip.v_registers[1] = 31;
+
+            chip.execute(0xD012);
+
+            assert!(pixel(&chip, 63, 31));
+            assert_eq!(pixel(&chip, 0, 31), !clip_sprites);
+            assert_eq!(pixel(&chip, 63, 0), !clip_sprites);
+            assert_eq!(pixel(&chip, 0, 0), !clip_sprites);
+        }
+    }
+
+    #[test]
+    fn op_dxyn_sets_vf_when_vf_is_a_coordinate() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.ram[0x300] = 0b1000_0000;
+        chip.i_register = 0x300;
+        chip.v_registers[0xF] = 4;
+
+        chip.execute(0xDFF1);
+
+        assert!(pixel(&chip, 4, 4));
+        assert_eq!(chip.v_registers[0xF], 0);
+    }
+
+    #[test]
+    fn op_ex9e_and_exa1_check_keys() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[0] = 0xA;
+
+        chip.execute(0xE09E);
+        assert_eq!(chip.program_counter, 0x202);
+        chip.execute(0xE0A1);
+        assert_eq!(chip.program_counter, 0x204);
+
+        chip.key_press(0xA, true);
+        chip.execute(0xE09E);
+        assert_eq!(chip.program_counter, 0x206);
+        chip.execute(0xE0A1);
+        assert_eq!(chip.program_counter, 0x206);
+    }
+
+    #[test]
+    fn op_fx0a_waits_for_a_key() {
+        let mut chip = machine(NO_QUIRKS);
+
+        chip.execute(0xF30A);
+        assert_eq!(chip.program_counter, 0x200);
+
+        chip.program_counter = 0x202;
+        chip.key_press(0x7, true);
+        chip.execute(0xF30A);
+        assert_eq!(chip.program_counter, 0x202);
+        assert_eq!(chip.v_registers[3], 0x7);
+    }
+
+    #[test]
+    fn timer_instructions() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.v_registers[1] = 42;
+
+        chip.execute(0xF115);
+        assert_eq!(chip.delay_timer, 42);
+
+        chip.execute(0xF118);
+        assert_eq!(chip.sound_timer, 42);
+
+        chip.delay_timer = 17;
+        chip.execute(0xF207);
+        assert_eq!(chip.v_registers[2], 17);
+    }
+
+    #[test]
+    fn op_fx1e_adds_to_i() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.i_register = 0x100;
+        chip.v_registers[0xF] = 0x55;
+        chip.v_registers[2] = 0x20;
+
+        chip.execute(0xF21E);
+
+        assert_eq!(chip.i_register, 0x120);
+        assert_eq!(chip.v_registers[0xF], 0x55);
+    }
+
+    #[test]
+    fn op_fx29_points_to_font_digits() {
+        let mut chip = machine(NO_QUIRKS);
+        for digit in 0..16 {
+            chip.v_registers[0] = digit;
+            chip.execute(0xF029);
+            assert_eq!(chip.i_register, digit as u16 * 5);
+            assert_eq!(
+                chip.ram[chip.i_register as usize..][..5],
+                Font::default().small()[digit as usize * 5..][..5]
+            );
+        }
+
+        chip.v_registers[0] = 0xA7;
+        chip.execute(0xF029);
+        assert_eq!(chip.i_register, 7 * 5);
+    }
+
+    #[test]
+    fn op_fx33_stores_decimal_digits_of_every_value() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.i_register = 0x300;
+
+        for value in 0..=255u8 {
+            chip.v_registers[4] = value;
+            chip.execute(0xF433);
+            assert_eq!(
+                chip.ram[0x300..0x303],
+                [value / 100, value / 10 % 10, value % 10],
+                "{}",
+                value
+            );
+            assert_eq!(chip.i_register, 0x300);
+        }
+    }
+
+    #[test]
+    fn op_fx55_and_fx65_store_and_load_registers() {
+        for memory_increment in [true, false] {
+            let mut chip = machine(Quirks {
+                memory_increment,
+                ..NO_QUIRKS
+            });
+            for x in 0..16 {
+                chip.v_registers[x] = x as u8 + 1;
+            }
+            chip.i_register = 0x300;
+
+            chip.execute(0xF355);
+            assert_eq!(chip.ram[0x300..0x305], [1, 2, 3, 4, 0]);
+            assert_eq!(
+                chip.i_register,
+                if memory_increment { 0x304 } else { 0x300 }
+            );
+
+            chip.v_registers = [0; 16];
+            chip.i_register = 0x300;
+            chip.execute(0xF265);
+            assert_eq!(chip.v_registers[..4], [1, 2, 3, 0]);
+            assert_eq!(
+                chip.i_register,
+                if memory_increment { 0x303 } else { 0x300 }
+            );
+        }
+    }
+
+    // Untrusted ROMs must never crash the emulator, the cases below were found by fuzzing
+
+    #[test]
+    fn a_full_stack_holds_every_return_address() {
+        let mut chip = machine(NO_QUIRKS);
+
+        for call in 0..16 {
+            chip.program_counter = 0x200 + call * 2;
+            chip.execute(0x2300);
+        }
+        assert_eq!(chip.stack_pointer, 16);
+
+        for call in (0..16).rev() {
+            chip.execute(0x00EE);
+            assert_eq!(chip.program_counter, 0x200 + call * 2);
+        }
+        assert_eq!(chip.stack_pointer, 0);
+    }
+
+    #[test]
+    fn deep_calls_drop_the_oldest_return_address() {
+        let mut chip = machine(NO_QUIRKS);
+
+        for call in 0..17 {
+            chip.program_counter = 0x200 + call * 2;
+            chip.execute(0x2300);
+        }
+        assert_eq!(chip.stack_pointer, 16);
+        assert_eq!(chip.stack[0], 0x202);
+        assert_eq!(chip.stack[15], 0x220);
+    }
+
+    #[test]
+    fn stray_returns_are_ignored() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.program_counter = 0x234;
+
+        chip.execute(0x00EE);
+        assert_eq!(chip.stack_pointer, 0);
+        assert_eq!(chip.program_counter, 0x234);
+    }
+
+    #[test]
+    fn program_counter_wraps_at_the_end_of_ram() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.program_counter = 0xFFF;
+        chip.ram[0xFFF] = 0x12;
+        chip.ram[0] = 0x34;
+
+        assert_eq!(chip.next_opcode(), 0x1234);
+        chip.run_instructions(1);
+        assert_eq!(chip.program_counter, 0x234);
+
+        // Jumps past the end of RAM land at the start of it
+        chip.v_registers[0] = 0xFF;
+        chip.ram[0xFE..0x100].copy_from_slice(&[0x56, 0x78]);
+        chip.execute(0xBFFF);
+        assert_eq!(chip.next_opcode(), 0x5678);
+    }
+
+    #[test]
+    fn memory_accesses_past_the_end_of_ram_wrap() {
+        let mut chip = machine(Quirks {
+            memory_increment: true,
+            ..NO_QUIRKS
+        });
+        chip.v_registers = [7; 16];
+        chip.i_register = 0xFFE;
+
+        chip.execute(0xF355);
+        assert_eq!(chip.ram[0xFFE..], [7, 7]);
+        assert_eq!(chip.ram[..2], [7, 7]);
+
+        chip.i_register = 0xFFF;
+        chip.execute(0xF065);
+        chip.execute(0xF033);
+        chip.execute(0xD00F);
+        chip.i_register = 0xFFFF;
+        chip.execute(0xFF55);
+        assert_eq!(chip.i_register, 0xF);
+    }
+
+    #[test]
+    fn key_skips_only_use_the_low_nibble() {
+        let mut chip = machine(NO_QUIRKS);
+        chip.keyboard[0x3] = true;
+        chip.v_registers[1] = 0xF3;
+
+        chip.execute(0xE19E);
+        assert_eq!(chip.program_counter, 0x204);
+        chip.execute(0xE1A1);
+        assert_eq!(chip.program_counter, 0x204);
     }
 }
@@ -0,0 +1,123 @@
+use std::{fmt, str::FromStr};
+
+///
+/// Colours used to display the monochrome CHIP-8 screen.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub background: (u8, u8, u8),
+    pub foreground: (u8, u8, u8),
+}
+
+const PRESETS: [(&str, Palette); 4] = [
+    (
+        "classic",
+        Palette {
+            background: (6, 138, 41),
+            foreground: (255, 255, 255),
+        },
+    ),
+    (
+        "mono",
+        Palette {
+            background: (0, 0, 0),
+            foreground: (255, 255, 255),
+        },
+    ),
+    (
+        "amber",
+        Palette {
+            background: (26, 16, 0),
+            foreground: (255, 176, 0),
+        },
+    ),
+    (
+        "lcd",
+        Palette {
+            background: (155, 188, 15),
+            foreground: (15, 56, 15),
+        },
+    ),
+];
+
+impl Palette {
+    ///
+    /// Returns the colour of a pixel in the given state
+    ///
+    pub fn color(&self, lit: bool) -> (u8, u8, u8) {
+        if lit {
+            self.foreground
+        } else {
+            self.background
+        }
+    }
+
+    pub fn preset_names() -> impl Iterator<Item = &'static str> {
+        PRESETS.iter().map(|(name, _)| *name)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        PRESETS[0].1
+    }
+}
+
+fn parse_hex_color(s: &str) -> Option<(u8, u8, u8)> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+///
+/// Accepts either the name of a preset or a custom `BACKGROUND:FOREGROUND` pair
+/// of hex colours, e.g. `000000:33ff66`
+///
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, palette)) = PRESETS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+        {
+            return Ok(*palette);
+        }
+
+        s.split_once(':')
+            .and_then(|(bg, fg)| {
+                Some(Palette {
+                    background: parse_hex_color(bg)?,
+                    foreground: parse_hex_color(fg)?,
+                })
+            })
+            .ok_or_else(|| {
+                let names: Vec<_> = Palette::preset_names().collect();
+                format!(
+                    "invalid palette '{}' (expected one of: {}, or BACKGROUND:FOREGROUND hex colours)",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((name, _)) = PRESETS.iter().find(|(_, palette)| palette == self) {
+            return f.write_str(name);
+        }
+
+        let (br, bg, bb) = self.background;
+        let (fr, fg, fb) = self.foreground;
+        write!(
+            f,
+            "{:02x}{:02x}{:02x}:{:02x}{:02x}{:02x}",
+            br, bg, bb, fr, fg, fb
+        )
+    }
+}
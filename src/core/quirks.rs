@@ -0,0 +1,111 @@
+use std::{fmt, str::FromStr};
+
+///
+/// Behavioural differences between the various CHIP-8 interpreters.
+/// Reference: https://github.com/Timendus/chip8-test-suite#quirks-test
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY1`, `8XY2` and `8XY3` reset VF to 0
+    pub vf_reset: bool,
+    /// `FX55` and `FX65` increment I by X + 1
+    pub memory_increment: bool,
+    /// `8XY6` and `8XYE` shift Vy into Vx instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    /// Sprites drawn past the edge of the screen are clipped instead of wrapped
+    pub clip_sprites: bool,
+}
+
+///
+/// Interpreters whose quirks can be selected as a preset.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Platform {
+    /// How this emulator behaved before quirks could be selected: sprites wrap around,
+    /// `FX55` and `FX65` leave I alone and shifts read Vy
+    #[default]
+    Legacy,
+    /// The original interpreter for the RCA COSMAC VIP
+    CosmacVip,
+    /// CHIP-48 for the HP-48 calculators
+    Chip48,
+    /// SUPER-CHIP 1.1 for the HP-48 calculators
+    SuperChip,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 4] = [
+        Platform::Legacy,
+        Platform::CosmacVip,
+        Platform::Chip48,
+        Platform::SuperChip,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Legacy => "legacy",
+            Platform::CosmacVip => "cosmac-vip",
+            Platform::Chip48 => "chip-48",
+            Platform::SuperChip => "super-chip",
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Platform::Legacy => Quirks {
+                vf_reset: false,
+                memory_increment: false,
+                shift_uses_vy: true,
+                clip_sprites: false,
+            },
+            Platform::CosmacVip => Quirks {
+                vf_reset: true,
+                memory_increment: true,
+                shift_uses_vy: true,
+                clip_sprites: true,
+            },
+            Platform::Chip48 => Quirks {
+                vf_reset: false,
+                memory_increment: true,
+                shift_uses_vy: false,
+                clip_sprites: true,
+            },
+            Platform::SuperChip => Quirks {
+                vf_reset: false,
+                memory_increment: false,
+                shift_uses_vy: false,
+                clip_sprites: true,
+            },
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Platform::default().quirks()
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Platform::ALL
+            .into_iter()
+            .find(|platform| platform.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Platform::ALL.iter().map(Platform::name).collect();
+                format!(
+                    "unknown platform '{}' (expected one of: {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
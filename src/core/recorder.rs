@@ -0,0 +1,234 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use gif::{Encoder, Frame, Repeat};
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use crate::{
+    palette::Palette,
+    screenshot::indexed_pixels,
+    sound::{SampleGenerator, SAMPLES_PER_FRAME, SAMPLE_RATE},
+    Chip8, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+/// Frames are captured at the 60 Hz rate of the timers
+const FRAMES_PER_SECOND: u64 = 60;
+
+/// Viewers treat GIF delays below 2/100 s as "as slow as possible", so shorter frames are merged
+const MIN_GIF_DELAY: u64 = 2;
+
+///
+/// Captures the screen once per emulated frame.
+///
+pub trait FrameRecorder {
+    fn record(&mut self, chip: &Chip8) -> io::Result<()>;
+
+    ///
+    /// Writes out anything still buffered and closes the recording
+    ///
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+struct PendingFrame {
+    pixels: Vec<u8>,
+    // Start of the frame in 1/100 s, the unit of GIF frame delays
+    start: u64,
+}
+
+///
+/// Records an animated GIF with the palette's two colours. Consecutive identical
+/// frames are stored once with a longer delay, keeping recordings of mostly static
+/// games small.
+///
+pub struct GifRecorder<W: Write> {
+    encoder: Encoder<W>,
+    scale: u32,
+    pending: Option<PendingFrame>,
+    frames_recorded: u64,
+}
+
+impl<W: Write> GifRecorder<W> {
+    pub fn new(writer: W, scale: u32, palette: &Palette) -> io::Result<Self> {
+        let (br, bg, bb) = palette.background;
+        let (fr, fg, fb) = palette.foreground;
+        let width = (SCREEN_WIDTH as u32 * scale) as u16;
+        let height = (SCREEN_HEIGHT as u32 * scale) as u16;
+
+        let mut encoder = Encoder::new(writer, width, height, &[br, bg, bb, fr, fg, fb])
+            .map_err(io::Error::other)?;
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(io::Error::other)?;
+
+        Ok(GifRecorder {
+            encoder,
+            scale,
+            pending: None,
+            frames_recorded: 0,
+        })
+    }
+
+    fn elapsed_centiseconds(&self) -> u64 {
+        self.frames_recorded * 100 / FRAMES_PER_SECOND
+    }
+
+    fn write_pending(&mut self, end: u64) -> io::Result<()> {
+        if let Some(pending) = self.pending.take() {
+            let mut frame = Frame {
+                width: (SCREEN_WIDTH as u32 * self.scale) as u16,
+                height: (SCREEN_HEIGHT as u32 * self.scale) as u16,
+                delay: (end - pending.start).clamp(MIN_GIF_DELAY, u16::MAX as u64) as u16,
+                ..Frame::default()
+            };
+            frame.buffer = pending.pixels.into();
+
+            self.encoder.write_frame(&frame).map_err(io::Error::other)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> FrameRecorder for GifRecorder<W> {
+    fn record(&mut self, chip: &Chip8) -> io::Result<()> {
+        let now = self.elapsed_centiseconds();
+        self.frames_recorded += 1;
+
+        let pixels = indexed_pixels(chip, self.scale);
+        match &mut self.pending {
+            Some(pending) if pending.pixels == pixels => (),
+            // Too short to be shown on its own, the newer frame replaces it
+            Some(pending) if now - pending.start < MIN_GIF_DELAY => pending.pixels = pixels,
+            _ => {
+                self.write_pending(now)?;
+                self.pending = Some(PendingFrame { pixels, start: now });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        let end = self.elapsed_centiseconds();
+        self.write_pending(end)?;
+
+        self.encoder.into_inner()?.flush()
+    }
+}
+
+///
+/// Writes every frame as raw 24-bit RGB pixels, suitable for piping into an encoder.
+/// Frames are the screen size times the scale, e.g. with a scale of 10:
+/// `ffmpeg -f rawvideo -pixel_format rgb24 -video_size 640x320 -framerate 60 -i - out.mp4`
+///
+pub struct RawFrameWriter<W: Write> {
+    writer: W,
+    scale: u32,
+    palette: Palette,
+}
+
+impl<W: Write> RawFrameWriter<W> {
+    pub fn new(writer: W, scale: u32, palette: &Palette) -> Self {
+        RawFrameWriter {
+            writer,
+            scale,
+            palette: *palette,
+        }
+    }
+}
+
+impl<W: Write> FrameRecorder for RawFrameWriter<W> {
+    fn record(&mut self, chip: &Chip8) -> io::Result<()> {
+        let rgb: Vec<u8> = indexed_pixels(chip, self.scale)
+            .into_iter()
+            .flat_map(|index| {
+                let (r, g, b) = self.palette.color(index != 0);
+                [r, g, b]
+            })
+            .collect();
+
+        self.writer.write_all(&rgb)
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+///
+/// Records the beeper as a 16-bit mono WAV file. The samples are generated from the
+/// sound timer rather than captured from the audio device, so muted and headless runs
+/// can be recorded too.
+///
+pub struct WavRecorder {
+    writer: WavWriter<BufWriter<File>>,
+    generator: SampleGenerator,
+    samples: Vec<i16>,
+}
+
+impl WavRecorder {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+
+        Ok(WavRecorder {
+            writer: WavWriter::create(path, spec).map_err(io::Error::other)?,
+            generator: SampleGenerator::new(),
+            samples: Vec::with_capacity(SAMPLES_PER_FRAME),
+        })
+    }
+}
+
+impl FrameRecorder for WavRecorder {
+    fn record(&mut self, chip: &Chip8) -> io::Result<()> {
+        self.samples.clear();
+        self.generator
+            .generate_frame(chip.is_beeping(), &mut self.samples);
+
+        for &sample in &self.samples {
+            self.writer.write_sample(sample).map_err(io::Error::other)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.writer.finalize().map_err(io::Error::other)
+    }
+}
+
+///
+/// Creates a GIF recording at the given path
+///
+pub fn create_gif<P: AsRef<Path>>(
+    path: P,
+    scale: u32,
+    palette: &Palette,
+) -> io::Result<Box<dyn FrameRecorder>> {
+    let file = BufWriter::new(File::create(path)?);
+    Ok(Box::new(GifRecorder::new(file, scale, palette)?))
+}
+
+///
+/// Creates a raw RGB recording at the given path, or on stdout if the path is `-`
+///
+pub fn create_raw<P: AsRef<Path>>(
+    path: P,
+    scale: u32,
+    palette: &Palette,
+) -> io::Result<Box<dyn FrameRecorder>> {
+    let writer: Box<dyn Write> = if path.as_ref() == Path::new("-") {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(BufWriter::new(File::create(path)?))
+    };
+
+    Ok(Box::new(RawFrameWriter::new(writer, scale, palette)))
+}
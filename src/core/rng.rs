@@ -0,0 +1,142 @@
+use std::{fmt, str::FromStr};
+
+///
+/// Algorithms the machine can use to produce the random numbers of `CXNN`.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RandomSource {
+    /// SplitMix64, a fast generator with good statistical quality
+    #[default]
+    SplitMix,
+    /// The routine of the original COSMAC VIP interpreter, which walks an 8-bit pointer
+    /// through the first page of memory and adds each byte it reads to the previous result.
+    /// On the VIP that page holds the interpreter itself; here it holds the font, so the
+    /// numbers are as poor as the original's but not the same sequence.
+    CosmacVip,
+}
+
+impl RandomSource {
+    pub const ALL: [RandomSource; 2] = [RandomSource::SplitMix, RandomSource::CosmacVip];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RandomSource::SplitMix => "splitmix64",
+            RandomSource::CosmacVip => "cosmac-vip",
+        }
+    }
+}
+
+impl fmt::Display for RandomSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for RandomSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RandomSource::ALL
+            .into_iter()
+            .find(|source| source.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = RandomSource::ALL.iter().map(RandomSource::name).collect();
+                format!(
+                    "unknown random source '{}' (expected one of: {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+///
+/// Small seedable pseudo-random number generator owned by the machine,
+/// so that runs can be reproduced from their seed.
+/// Reference: https://prng.di.unimi.it/splitmix64.c
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rng {
+    source: RandomSource,
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn seeded(seed: u64) -> Self {
+        Rng::with_source(RandomSource::default(), seed)
+    }
+
+    pub fn with_source(source: RandomSource, seed: u64) -> Self {
+        Rng {
+            source,
+            seed,
+            state: seed,
+        }
+    }
+
+    ///
+    /// Creates a generator with a seed taken from the operating system's entropy
+    ///
+    pub fn from_entropy() -> Self {
+        Rng::seeded(rand::random())
+    }
+
+    ///
+    /// Restores a generator from the values returned by `source`, `seed` and `state`
+    ///
+    pub fn from_parts(source: RandomSource, seed: u64, state: u64) -> Self {
+        Rng {
+            source,
+            seed,
+            state,
+        }
+    }
+
+    pub fn source(&self) -> RandomSource {
+        self.source
+    }
+
+    ///
+    /// Seed the generator was created with
+    ///
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    ///
+    /// Current internal state, which together with the source is enough to continue the sequence
+    ///
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    ///
+    /// Returns the next random byte. `memory` is the machine's RAM, which is only
+    /// read by the COSMAC VIP routine
+    ///
+    pub fn next_u8(&mut self, memory: &[u8]) -> u8 {
+        match self.source {
+            RandomSource::SplitMix => (self.next_u64() >> 56) as u8,
+            RandomSource::CosmacVip => {
+                // The low byte of the state is the pointer, the next one the previous result.
+                // Machines with less than a page of RAM wrap it like any other address
+                let pointer = (self.state as u8).wrapping_add(1);
+                let byte = memory[pointer as usize & (memory.len() - 1)];
+                let value = ((self.state >> 8) as u8).wrapping_add(byte);
+
+                self.state = (self.state & !0xFFFF) | (value as u64) << 8 | pointer as u64;
+                value
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::time::Duration;
+
+use crate::{
+    timing::{self, TimingModel, VIP_CYCLES_PER_FRAME, VIP_DISPLAY_CYCLES},
+    Chip8,
+};
+
+/// The delay and sound timers always count down at 60 Hz
+pub const TIMER_FREQUENCY: u64 = 60;
+
+/// Default number of instructions executed per emulated second
+pub const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = 600;
+
+/// Longest stretch of wall-clock time emulated in one go. Anything above this,
+/// e.g. while the window is being dragged, is dropped instead of caught up on.
+const MAX_CATCH_UP: Duration = Duration::from_millis(100);
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// Highest speed multiplier, which keeps the emulated time of one call within `Duration`'s range
+pub const MAX_SPEED: f64 = 64.0;
+
+///
+/// Drives a machine from wall-clock time, independently of the display's refresh rate.
+/// Instructions run at the configured rate in between timer ticks, which happen at exactly 60 Hz.
+///
+pub struct Scheduler {
+    instructions_per_second: u32,
+    timing: TimingModel,
+    speed: f64,
+    // Cycles an instruction overran the previous frame by, only used by the VIP timing model
+    cycle_debt: u32,
+    // Emulated time since the last rate change, in nanoseconds
+    emulated_nanos: u128,
+    instructions_run: u128,
+    timer_ticks: u128,
+}
+
+impl Scheduler {
+    pub fn new(instructions_per_second: u32) -> Self {
+        Scheduler {
+            instructions_per_second,
+            timing: TimingModel::Fixed,
+            speed: 1.0,
+            cycle_debt: 0,
+            emulated_nanos: 0,
+            instructions_run: 0,
+            timer_ticks: 0,
+        }
+    }
+
+    pub fn instructions_per_second(&self) -> u32 {
+        self.instructions_per_second
+    }
+
+    pub fn set_instructions_per_second(&mut self, instructions_per_second: u32) {
+        self.instructions_per_second = instructions_per_second;
+        self.rebase();
+    }
+
+    pub fn timing(&self) -> TimingModel {
+        self.timing
+    }
+
+    ///
+    /// Selects how instruction costs are accounted for. With the VIP model the instruction
+    /// rate follows from the cost of each instruction and the configured rate is ignored.
+    ///
+    pub fn set_timing(&mut self, timing: TimingModel) {
+        self.timing = timing;
+        self.cycle_debt = 0;
+        self.rebase();
+    }
+
+    ///
+    /// Multiplier applied to elapsed wall-clock time, e.g. 4.0 for turbo or 0.25 for slow motion
+    ///
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    ///
+    /// Sets the speed multiplier, clamped to `0.0..=MAX_SPEED`. NaN stops the emulation.
+    ///
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = if speed.is_nan() {
+            0.0
+        } else {
+            speed.clamp(0.0, MAX_SPEED)
+        };
+    }
+
+    ///
+    /// Advances the machine by the given amount of wall-clock time (scaled by the speed).
+    /// Only whole frames are run, so inputs applied in between calls always land on a frame
+    /// boundary and a run can be reproduced frame by frame. Returns the number of frames run.
+    ///
+    pub fn run(&mut self, chip: &mut Chip8, elapsed: Duration) -> u32 {
+        self.run_with(chip, elapsed, |_| ())
+    }
+
+    ///
+    /// Same as `run`, calling `on_frame` with the machine right after each timer tick
+    ///
+    pub fn run_with<F: FnMut(&mut Chip8)>(
+        &mut self,
+        chip: &mut Chip8,
+        elapsed: Duration,
+        mut on_frame: F,
+    ) -> u32 {
+        let emulated = elapsed.min(MAX_CATCH_UP).mul_f64(self.speed);
+        self.emulated_nanos += emulated.as_nanos();
+
+        let mut frames = 0;
+        while self.next_tick_nanos() <= self.emulated_nanos {
+            self.run_frame(chip);
+            on_frame(chip);
+            frames += 1;
+        }
+
+        frames
+    }
+
+    ///
+    /// Runs exactly one 60 Hz frame: the instructions due until the next timer tick, followed by the tick.
+    /// Used for deterministic runs where no wall-clock time is involved.
+    ///
+    pub fn run_frame(&mut self, chip: &mut Chip8) {
+        let tick_at = self.next_tick_nanos();
+        match self.timing {
+            TimingModel::Fixed => {
+                let due = self.instructions_due(tick_at);
+                self.execute(chip, due);
+            }
+            TimingModel::CosmacVip => self.run_vip_frame(chip),
+        }
+
+        chip.tick_timers();
+        self.timer_ticks += 1;
+        self.emulated_nanos = self.emulated_nanos.max(tick_at);
+    }
+
+    ///
+    /// Spends the cycles left over by the display on instructions. An instruction which
+    /// overruns the frame eats into the next one, and `DXYN` ends the frame early as the
+    /// interpreter waits for the vertical blank before drawing.
+    ///
+    fn run_vip_frame(&mut self, chip: &mut Chip8) {
+        let budget = VIP_CYCLES_PER_FRAME - VIP_DISPLAY_CYCLES;
+        let mut used = self.cycle_debt;
+
+        while used < budget {
+            let op = chip.next_opcode();
+            let cycles = timing::vip_cycles(chip, op);
+            chip.tick();
+            self.instructions_run += 1;
+
+            if timing::waits_for_vblank(op) {
+                // The sprite is drawn once the interrupt has happened, i.e. at the start of the next frame
+                used = budget + cycles;
+                break;
+            }
+
+            used += cycles;
+        }
+
+        self.cycle_debt = used - budget;
+    }
+
+    fn next_tick_nanos(&self) -> u128 {
+        ((self.timer_ticks + 1) * NANOS_PER_SECOND).div_ceil(TIMER_FREQUENCY as u128)
+    }
+
+    fn instructions_due(&self, until_nanos: u128) -> u128 {
+        let total = until_nanos * self.instructions_per_second as u128 / NANOS_PER_SECOND;
+        total.saturating_sub(self.instructions_run)
+    }
+
+    fn execute(&mut self, chip: &mut Chip8, count: u128) {
+        chip.run_instructions(count as u64);
+        self.instructions_run += count;
+    }
+
+    ///
+    /// Restarts the bookkeeping from the current timer tick so that a rate change
+    /// doesn't make up for (or skip) instructions in the past. The time emulated since
+    /// that tick counts as already run at the new rate.
+    ///
+    fn rebase(&mut self) {
+        let last_tick_nanos =
+            (self.timer_ticks * NANOS_PER_SECOND).div_ceil(TIMER_FREQUENCY as u128);
+        self.emulated_nanos -= last_tick_nanos.min(self.emulated_nanos);
+        self.timer_ticks = 0;
+        self.instructions_run =
+            self.emulated_nanos * self.instructions_per_second as u128 / NANOS_PER_SECOND;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // V0 = FF, DT = V0, then loop forever
+    const ROM: [u8; 6] = [0x60, 0xFF, 0xF0, 0x15, 0x12, 0x04];
+
+    fn setup() -> (Chip8, Scheduler) {
+        let mut chip = Chip8::new(None);
+        chip.load_rom_bytes(&ROM);
+        (chip, Scheduler::new(DEFAULT_INSTRUCTIONS_PER_SECOND))
+    }
+
+    #[test]
+    fn frames_run_the_instructions_due_before_each_tick() {
+        let (mut chip, mut scheduler) = setup();
+
+        scheduler.run_frame(&mut chip);
+        assert_eq!(scheduler.instructions_run, 10);
+        assert_eq!(chip.delay_timer(), 0xFE);
+
+        for _ in 0..59 {
+            scheduler.run_frame(&mut chip);
+        }
+        assert_eq!(scheduler.instructions_run, 600);
+        assert_eq!(chip.delay_timer(), 0xFF - 60);
+    }
+
+    #[test]
+    fn timers_tick_at_60_hz() {
+        let (mut chip, mut scheduler) = setup();
+
+        assert_eq!(scheduler.run(&mut chip, Duration::from_millis(16)), 0);
+        assert_eq!(scheduler.run(&mut chip, Duration::from_millis(1)), 1);
+
+        let frames: u32 = (0..10)
+            .map(|_| scheduler.run(&mut chip, Duration::from_millis(100)))
+            .sum();
+        assert_eq!(frames, 60);
+        assert_eq!(scheduler.timer_ticks, 61);
+    }
+
+    #[test]
+    fn catch_up_is_capped() {
+        let (mut chip, mut scheduler) = setup();
+
+        assert_eq!(scheduler.run(&mut chip, Duration::from_secs(1)), 6);
+        assert_eq!(scheduler.instructions_run, 60);
+    }
+
+    #[test]
+    fn rate_changes_apply_from_the_last_tick() {
+        let (mut chip, mut scheduler) = setup();
+
+        scheduler.run_frame(&mut chip);
+        scheduler.set_instructions_per_second(1200);
+        assert_eq!(scheduler.instructions_run, 0);
+
+        scheduler.run_frame(&mut chip);
+        assert_eq!(scheduler.instructions_run, 20);
+    }
+
+    #[test]
+    fn rebase_is_idempotent() {
+        let (mut chip, mut scheduler) = setup();
+
+        // One tick at 16.67 ms plus 8.33 ms, i.e. 5 instructions at 600 per second, not yet run
+        assert_eq!(scheduler.run(&mut chip, Duration::from_millis(25)), 1);
+        scheduler.set_instructions_per_second(1200);
+        let rebased = (scheduler.emulated_nanos, scheduler.instructions_run);
+        scheduler.set_instructions_per_second(1200);
+        assert_eq!(
+            (scheduler.emulated_nanos, scheduler.instructions_run),
+            rebased
+        );
+        assert_eq!(scheduler.instructions_run, 9);
+
+        // The next tick is a whole frame after the rebase
+        assert_eq!(scheduler.run(&mut chip, Duration::from_millis(8)), 0);
+        assert_eq!(scheduler.run(&mut chip, Duration::from_millis(1)), 1);
+        assert_eq!(scheduler.instructions_run, 20);
+    }
+
+    #[test]
+    fn speed_is_clamped() {
+        let (mut chip, mut scheduler) = setup();
+
+        scheduler.set_speed(f64::INFINITY);
+        assert_eq!(scheduler.speed(), MAX_SPEED);
+        scheduler.run(&mut chip, Duration::MAX);
+
+        scheduler.set_speed(-1.0);
+        assert_eq!(scheduler.speed(), 0.0);
+        scheduler.set_speed(f64::NAN);
+        assert_eq!(scheduler.speed(), 0.0);
+        assert_eq!(scheduler.run(&mut chip, Duration::from_secs(1)), 0);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{palette::Palette, Chip8, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+///
+/// Expands the screen into one palette index (0 = off, 1 = on) per output pixel,
+/// with every CHIP-8 pixel blown up to a `scale` x `scale` square
+///
+pub fn indexed_pixels(chip: &Chip8, scale: u32) -> Vec<u8> {
+    let display = chip.display();
+    let scale = scale as usize;
+    let width = display.width() * scale;
+    let mut pixels = Vec::with_capacity(width * display.height() * scale);
+
+    for &row in display.rows() {
+        let line: Vec<u8> = (0..display.width())
+            .rev()
+            .flat_map(|bit| std::iter::repeat_n((row >> bit) as u8 & 1, scale))
+            .collect();
+
+        for _ in 0..scale {
+            pixels.extend_from_slice(&line);
+        }
+    }
+
+    pixels
+}
+
+///
+/// Encodes the current screen as an indexed PNG using the palette's colours.
+/// A scale of 1 produces an image at the native 64x32 resolution.
+///
+pub fn write_png<W: Write>(
+    chip: &Chip8,
+    scale: u32,
+    palette: &Palette,
+    writer: W,
+) -> io::Result<()> {
+    let width = SCREEN_WIDTH as u32 * scale;
+    let height = SCREEN_HEIGHT as u32 * scale;
+
+    let (br, bg, bb) = palette.background;
+    let (fr, fg, fb) = palette.foreground;
+
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(vec![br, bg, bb, fr, fg, fb]);
+
+    let mut png_writer = encoder.write_header()?;
+    png_writer.write_image_data(&indexed_pixels(chip, scale))?;
+    png_writer.finish()?;
+
+    Ok(())
+}
+
+///
+/// Saves the current screen as a PNG file
+///
+pub fn save_png<P: AsRef<Path>>(
+    chip: &Chip8,
+    scale: u32,
+    palette: &Palette,
+    path: P,
+) -> io::Result<()> {
+    let file = File::create(path)?;
+    write_png(chip, scale, palette, BufWriter::new(file))
+}
+
+///
+/// Builds a file name like `chip8_20240512_183005_123.png` from the current UTC time
+///
+pub fn timestamped_file_name(prefix: &str, extension: &str) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = now.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let time_of_day = seconds % 86_400;
+
+    format!(
+        "{}_{:04}{:02}{:02}_{:02}{:02}{:02}_{:03}.{}",
+        prefix,
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60,
+        now.subsec_millis(),
+        extension
+    )
+}
+
+///
+/// Converts days since the Unix epoch into a (year, month, day) date.
+/// Algorithm: https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+///
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
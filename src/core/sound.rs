@@ -0,0 +1,54 @@
+use std::f32::consts::TAU;
+
+use crate::scheduler::TIMER_FREQUENCY;
+
+/// Sample rate of the generated audio
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// Pitch of the beeper, matching the tone played through the audio device
+pub const BEEP_FREQUENCY: f32 = 700.0;
+
+const BEEP_AMPLITUDE: f32 = 0.30;
+
+/// Samples making up one 60 Hz frame
+pub const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE as u64 / TIMER_FREQUENCY) as usize;
+
+///
+/// Generates the beeper's output from the sound timer, one frame at a time.
+/// The output only depends on which frames were beeping, not on the host's audio device,
+/// so the same run always produces the same samples.
+///
+pub struct SampleGenerator {
+    // Position in the tone in samples, kept across frames so the wave doesn't click between beeping frames
+    phase: u64,
+}
+
+impl SampleGenerator {
+    pub fn new() -> Self {
+        SampleGenerator { phase: 0 }
+    }
+
+    ///
+    /// Appends the samples of one frame, a tone if the machine was beeping during the frame and silence otherwise
+    ///
+    pub fn generate_frame(&mut self, beeping: bool, samples: &mut Vec<i16>) {
+        if !beeping {
+            samples.extend(std::iter::repeat_n(0, SAMPLES_PER_FRAME));
+            return;
+        }
+
+        for _ in 0..SAMPLES_PER_FRAME {
+            let time = (self.phase % SAMPLE_RATE as u64) as f32 / SAMPLE_RATE as f32;
+            let sample = (TAU * BEEP_FREQUENCY * time).sin() * BEEP_AMPLITUDE;
+
+            samples.push((sample * i16::MAX as f32) as i16);
+            self.phase += 1;
+        }
+    }
+}
+
+impl Default for SampleGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
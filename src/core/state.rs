@@ -0,0 +1,315 @@
+//!
+//! Save states: a snapshot of everything that determines how the machine continues,
+//! including the random number generator, so a restored state replays identically.
+//!
+//! States are stored in a small little-endian binary format starting with `C8ST`
+//! and a version byte.
+//!
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    quirks::Quirks,
+    rng::{RandomSource, Rng},
+};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 1;
+
+///
+/// The complete state of a machine. The loaded ROM is part of `ram`, the audio
+/// output and input recording are not part of the state.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SaveState {
+    pub program_counter: u16,
+    pub i_register: u16,
+    pub stack_pointer: u16,
+    pub v_registers: [u8; 16],
+    pub stack: Vec<u16>,
+    pub ram: Vec<u8>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keyboard: [bool; 16],
+    pub screen: Vec<bool>,
+    pub quirks: Quirks,
+    pub rng: Rng,
+    pub frames: u64,
+    pub beeping: bool,
+}
+
+impl SaveState {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.ram.len() + self.screen.len() / 8 + 128);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+
+        bytes.extend_from_slice(&self.program_counter.to_le_bytes());
+        bytes.extend_from_slice(&self.i_register.to_le_bytes());
+        bytes.extend_from_slice(&self.stack_pointer.to_le_bytes());
+        bytes.extend_from_slice(&self.v_registers);
+
+        bytes.push(self.stack.len() as u8);
+        for entry in &self.stack {
+            bytes.extend_from_slice(&entry.to_le_bytes());
+        }
+
+        bytes.extend_from_slice(&(self.ram.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.ram);
+
+        bytes.push(self.delay_timer);
+        bytes.push(self.sound_timer);
+        bytes.extend_from_slice(&pack_bits(&self.keyboard));
+
+        bytes.extend_from_slice(&(self.screen.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&pack_bits(&self.screen));
+
+        bytes.extend_from_slice(&pack_bits(&[
+            self.quirks.vf_reset,
+            self.quirks.memory_increment,
+            self.quirks.shift_uses_vy,
+            self.quirks.clip_sprites,
+        ]));
+
+        let source = RandomSource::ALL
+            .iter()
+            .position(|source| *source == self.rng.source())
+            .unwrap();
+        bytes.push(source as u8);
+        bytes.extend_from_slice(&self.rng.seed().to_le_bytes());
+        bytes.extend_from_slice(&self.rng.state().to_le_bytes());
+
+        bytes.extend_from_slice(&self.frames.to_le_bytes());
+        bytes.push(self.beeping as u8);
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<SaveState, String> {
+        let mut reader = Reader { bytes };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err("not a save state".to_string());
+        }
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(format!("unsupported save state version {}", version));
+        }
+
+        let program_counter = reader.u16()?;
+        let i_register = reader.u16()?;
+        let stack_pointer = reader.u16()?;
+        let v_registers = reader.take(16)?.try_into().unwrap();
+
+        let stack_len = reader.u8()? as usize;
+        let stack = (0..stack_len)
+            .map(|_| reader.u16())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let ram_len = reader.u32()? as usize;
+        let ram = reader.take(ram_len)?.to_vec();
+
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let keyboard = unpack_bits(reader.take(2)?, 16).try_into().unwrap();
+
+        let screen_len = reader.u32()? as usize;
+        let screen = unpack_bits(reader.take(screen_len.div_ceil(8))?, screen_len);
+
+        let flags = unpack_bits(reader.take(1)?, 4);
+        let quirks = Quirks {
+            vf_reset: flags[0],
+            memory_increment: flags[1],
+            shift_uses_vy: flags[2],
+            clip_sprites: flags[3],
+        };
+
+        let source = *RandomSource::ALL
+            .get(reader.u8()? as usize)
+            .ok_or("unknown random source")?;
+        let rng = Rng::from_parts(source, reader.u64()?, reader.u64()?);
+
+        let frames = reader.u64()?;
+        let beeping = reader.u8()? != 0;
+
+        if !reader.bytes.is_empty() {
+            return Err("unexpected data after the save state".to_string());
+        }
+
+        Ok(SaveState {
+            program_counter,
+            i_register,
+            stack_pointer,
+            v_registers,
+            stack,
+            ram,
+            delay_timer,
+            sound_timer,
+            keyboard,
+            screen,
+            quirks,
+            rng,
+            frames,
+            beeping,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::File::create(path)?.write_all(&self.to_bytes())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<SaveState> {
+        let bytes = fs::read(path)?;
+        SaveState::from_bytes(&bytes)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (i, &bit)| byte | (bit as u8) << i)
+        })
+        .collect()
+}
+
+fn unpack_bits(bytes: &[u8], count: usize) -> Vec<bool> {
+    (0..count)
+        .map(|i| bytes[i / 8] & (1 << (i % 8)) != 0)
+        .collect()
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < count {
+            return Err("save state is truncated".to_string());
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip8;
+
+    fn machine(source: RandomSource) -> Chip8 {
+        let mut chip = Chip8::builder()
+            .random_source(source)
+            .seed(1234)
+            .build()
+            .unwrap();
+        chip.load_rom_bytes(&[0x60, 0x05, 0xA2, 0x10, 0x12, 0x00]);
+        chip
+    }
+
+    fn random_bytes(chip: &mut Chip8, count: usize) -> Vec<u8> {
+        (0..count)
+            .map(|_| {
+                chip.execute(0xC0FF);
+                chip.v_registers()[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn states_round_trip_through_bytes() {
+        for source in RandomSource::ALL {
+            let mut chip = machine(source);
+            chip.run_instructions(2);
+            random_bytes(&mut chip, 3);
+            chip.key_press(0xA, true);
+
+            let state = chip.save_state();
+            let restored = SaveState::from_bytes(&state.to_bytes()).unwrap();
+            assert_eq!(restored, state);
+            assert_eq!(restored.rng.source(), source);
+        }
+    }
+
+    #[test]
+    fn random_numbers_continue_identically_after_loading() {
+        for source in RandomSource::ALL {
+            let mut chip = machine(source);
+            random_bytes(&mut chip, 5);
+            let bytes = chip.save_state().to_bytes();
+            let expected = random_bytes(&mut chip, 16);
+
+            let mut restored = machine(source);
+            restored
+                .load_state(&SaveState::from_bytes(&bytes).unwrap())
+                .unwrap();
+            assert_eq!(random_bytes(&mut restored, 16), expected);
+        }
+    }
+
+    #[test]
+    fn truncated_and_padded_states_are_rejected() {
+        let bytes = machine(RandomSource::SplitMix).save_state().to_bytes();
+        for length in 0..bytes.len() {
+            assert!(SaveState::from_bytes(&bytes[..length]).is_err());
+        }
+
+        let mut padded = bytes.clone();
+        padded.push(0);
+        assert!(SaveState::from_bytes(&padded).is_err());
+    }
+
+    #[test]
+    fn foreign_files_and_versions_are_rejected() {
+        let bytes = machine(RandomSource::SplitMix).save_state().to_bytes();
+
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        assert_eq!(
+            SaveState::from_bytes(&magic),
+            Err("not a save state".to_string())
+        );
+
+        let mut version = bytes.clone();
+        version[MAGIC.len()] = VERSION + 1;
+        assert!(SaveState::from_bytes(&version).is_err());
+    }
+
+    #[test]
+    fn states_only_load_into_machines_of_the_same_size() {
+        let small = Chip8::builder().ram_size(0x800).build().unwrap();
+        let mut chip = Chip8::new(None);
+        let before = chip.save_state();
+        assert!(chip.load_state(&small.save_state()).is_err());
+        assert_eq!(chip.save_state(), before);
+
+        let mut deep = Chip8::builder().stack_depth(32).build().unwrap();
+        assert!(deep.load_state(&chip.save_state()).is_err());
+        assert!(chip.load_state(&deep.save_state()).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+//!
+//! Instruction timing of the original COSMAC VIP interpreter.
+//!
+//! The VIP's CDP1802 runs at 1.76 MHz with 8 clock periods per machine cycle, which leaves
+//! 3668 machine cycles per 60 Hz frame. The display DMA and the timer interrupt steal part of
+//! every frame and the remainder is spent interpreting CHIP-8 instructions, each of which costs
+//! a fetch/decode overhead plus the cycles of its handler routine.
+//!
+//! The cycle counts are taken from the instruction timings Jackson Sommerich measured in
+//! "Chip-8 Instruction Scheduling and Frequency" (2019), which follow the interpreter
+//! listing in Laurence Scotford's "Chip-8 on the COSMAC VIP" series. Data dependent costs,
+//! e.g. taken skips, unaligned sprites and the BCD conversion loops, follow the machine state.
+//!
+use std::{fmt, str::FromStr};
+
+use crate::Chip8;
+
+/// Machine cycles in one 60 Hz frame
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+
+/// Cycles of every frame lost to the display DMA (128 scanlines of 8 bytes) and the interrupt routine
+pub const VIP_DISPLAY_CYCLES: u32 = 1024 + 46;
+
+/// Cycles spent fetching and decoding any instruction before its handler runs
+const FETCH_CYCLES: u32 = 40;
+
+/// Cycles `00E0` takes to zero all 256 bytes of display memory, longer than a whole frame
+const CLEAR_CYCLES: u32 = 24 + 3054;
+
+///
+/// How the time taken by each instruction is accounted for.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimingModel {
+    /// Every instruction takes the same time, a fixed number of them run per second
+    #[default]
+    Fixed,
+    /// Instructions take as long as they did on the COSMAC VIP and `DXYN` waits for the vertical blank
+    CosmacVip,
+}
+
+impl TimingModel {
+    pub const ALL: [TimingModel; 2] = [TimingModel::Fixed, TimingModel::CosmacVip];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimingModel::Fixed => "fixed",
+            TimingModel::CosmacVip => "cosmac-vip",
+        }
+    }
+}
+
+impl fmt::Display for TimingModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for TimingModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TimingModel::ALL
+            .into_iter()
+            .find(|model| model.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = TimingModel::ALL.iter().map(TimingModel::name).collect();
+                format!(
+                    "unknown timing model '{}' (expected one of: {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+///
+/// Returns true for `DXYN`, which waits for the next vertical blank before drawing on the VIP
+///
+pub fn waits_for_vblank(op: u16) -> bool {
+    op & 0xF000 == 0xD000
+}
+
+///
+/// Machine cycles the VIP interpreter takes to execute `op` in the machine's current state.
+/// For `DXYN` this is only the drawing itself, the wait for the vertical blank comes on top.
+///
+pub fn vip_cycles(chip: &Chip8, op: u16) -> u32 {
+    let x = ((op & 0x0F00) >> 8) as usize;
+    let y = ((op & 0x00F0) >> 4) as usize;
+    let n = (op & 0x000F) as u32;
+    let nn = (op & 0x00FF) as u8;
+    let vx = chip.v_registers[x];
+    let vy = chip.v_registers[y];
+
+    // Skips take a couple of extra cycles to advance the program counter
+    let skip = |taken: bool| if taken { 14 } else { 10 };
+    let key_held = chip.keyboard()[(vx & 0xF) as usize];
+
+    let execute = match (op & 0xF000) >> 12 {
+        0x0 => match op {
+            0x00E0 => CLEAR_CYCLES,
+            0x00EE => 10,
+            _ => 0,
+        },
+        0x1 => 12,
+        0x2 => 26,
+        0x3 => skip(vx == nn),
+        0x4 => skip(vx != nn),
+        0x5 => skip(vx == vy) + 4,
+        0x6 => 6,
+        0x7 => 10,
+        0x8 => match op & 0x000F {
+            0x0 => 12,
+            // The ALU operations run a generated 1802 instruction and store VF
+            _ => 44,
+        },
+        0x9 => skip(vx != vy) + 4,
+        0xA => 12,
+        0xB => 22,
+        0xC => 36,
+        0xD => draw_cycles(vx, n),
+        0xE => match op & 0x00FF {
+            0x9E => skip(key_held) + 4,
+            0xA1 => skip(!key_held) + 4,
+            _ => 0,
+        },
+        0xF => match op & 0x00FF {
+            0x07 | 0x15 | 0x18 => 10,
+            0x0A => 12,
+            0x1E => 16,
+            0x29 => 20,
+            // BCD conversion is done by repeated subtraction, one loop per unit of each digit
+            0x33 => 84 + 16 * (vx / 100 + vx / 10 % 10 + vx % 10) as u32,
+            0x55 | 0x65 => 14 + 14 * (x as u32 + 1),
+            _ => 0,
+        },
+        _ => 0,
+    };
+
+    FETCH_CYCLES + execute
+}
+
+///
+/// Cycles `DXYN` takes to draw `rows` rows at column `vx`, not counting the wait for the
+/// vertical blank. Sprites which aren't byte aligned straddle two bytes of display memory
+/// per row.
+///
+fn draw_cycles(vx: u8, rows: u32) -> u32 {
+    let per_row = if vx.is_multiple_of(8) { 34 } else { 46 };
+    26 + rows * per_row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cycles(op: u16, registers: &[(usize, u8)]) -> u32 {
+        let mut chip = Chip8::new(None);
+        for &(index, value) in registers {
+            chip.set_v_register(index, value).unwrap();
+        }
+        vip_cycles(&chip, op) - FETCH_CYCLES
+    }
+
+    #[test]
+    fn known_instruction_costs() {
+        assert_eq!(cycles(0x00E0, &[]), 3078);
+        assert_eq!(cycles(0x6A12, &[]), 6);
+        assert_eq!(cycles(0x8120, &[]), 12);
+        assert_eq!(cycles(0x8124, &[]), 44);
+        assert_eq!(cycles(0xF565, &[]), 98);
+    }
+
+    #[test]
+    fn skips_cost_more_when_taken() {
+        assert_eq!(cycles(0x3105, &[(1, 5)]), 14);
+        assert_eq!(cycles(0x3105, &[(1, 4)]), 10);
+        assert_eq!(cycles(0x9120, &[(1, 1)]), 18);
+        assert_eq!(cycles(0xE1A1, &[]), 18);
+        assert_eq!(cycles(0xE19E, &[]), 14);
+    }
+
+    #[test]
+    fn bcd_loops_once_per_unit_of_each_digit() {
+        assert_eq!(cycles(0xF033, &[(0, 0)]), 84);
+        assert_eq!(cycles(0xF033, &[(0, 255)]), 84 + 16 * 12);
+    }
+
+    #[test]
+    fn drawing_depends_on_height_and_position() {
+        assert_eq!(cycles(0xD015, &[]), 26 + 5 * 34);
+        assert_eq!(cycles(0xD01F, &[]), 26 + 15 * 34);
+        assert_eq!(cycles(0xD015, &[(0, 3)]), 26 + 5 * 46);
+        assert_eq!(cycles(0xD015, &[(0, 16)]), 26 + 5 * 34);
+        assert!(waits_for_vblank(0xD015));
+        assert!(!waits_for_vblank(0x00E0));
+    }
+}
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, fs};
+
+use sdl2::keyboard::Keycode;
+
+/*
+    Keyboard                    Chip-8
+    +---+---+---+---+           +---+---+---+---+
+    | 1 | 2 | 3 | 4 |           | 1 | 2 | 3 | C |
+    +---+---+---+---+           +---+---+---+---+
+    | Q | W | E | R |           | 4 | 5 | 6 | D |
+    +---+---+---+---+     =>    +---+---+---+---+
+    | A | S | D | F |           | 7 | 8 | 9 | E |
+    +---+---+---+---+           +---+---+---+---+
+    | Z | X | C | V |           | A | 0 | B | F |
+    +---+---+---+---+           +---+---+---+---+
+*/
+const DEFAULT_BINDINGS: [(Keycode, usize); 16] = [
+    (Keycode::Num1, 0x1),
+    (Keycode::Num2, 0x2),
+    (Keycode::Num3, 0x3),
+    (Keycode::Num4, 0xC),
+    (Keycode::Q, 0x4),
+    (Keycode::W, 0x5),
+    (Keycode::E, 0x6),
+    (Keycode::R, 0xD),
+    (Keycode::A, 0x7),
+    (Keycode::S, 0x8),
+    (Keycode::D, 0x9),
+    (Keycode::F, 0xE),
+    (Keycode::Z, 0xA),
+    (Keycode::X, 0x0),
+    (Keycode::C, 0xB),
+    (Keycode::V, 0xF),
+];
+
+///
+/// Maps keyboard keys to the buttons of the CHIP-8 keypad
+///
+pub struct Keymap {
+    bindings: HashMap<Keycode, usize>,
+}
+
+impl Keymap {
+    ///
+    /// Loads key bindings from a file with one `<key name> = <hex button>` binding per line,
+    /// e.g. `Up = 5`. Key names follow SDL's naming, and lines starting with `#` are ignored.
+    /// The file extends the default bindings: `Up = 5` makes Up press 5 as well as W, while
+    /// binding a default key like `W = 2` moves that key to the new button.
+    ///
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read keymap '{}': {}", path, e))?;
+        Keymap::parse(&contents).map_err(|error| format!("{}:{}", path, error))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut keymap = Keymap::default();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |message: &str| format!("{}: {}", number + 1, message);
+            let (name, button) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected '<key name> = <hex button>'"))?;
+            let button = usize::from_str_radix(button.trim(), 16)
+                .ok()
+                .filter(|button| *button < 16)
+                .ok_or_else(|| error("button must be a hex digit between 0 and F"))?;
+            let key = Keycode::from_name(name.trim()).ok_or_else(|| error("unknown key name"))?;
+
+            keymap.bind(key, button);
+        }
+
+        Ok(keymap)
+    }
+
+    ///
+    /// Makes `key` press `button`, replacing only that key's previous binding
+    ///
+    pub fn bind(&mut self, key: Keycode, button: usize) {
+        self.bindings.insert(key, button);
+    }
+
+    pub fn button(&self, key: Keycode) -> Option<usize> {
+        self.bindings.get(&key).copied()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap {
+            bindings: DEFAULT_BINDINGS.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_extend_the_defaults() {
+        let mut keymap = Keymap::default();
+        keymap.bind(Keycode::Up, 0x5);
+        keymap.bind(Keycode::Q, 0xC);
+
+        assert_eq!(keymap.button(Keycode::Up), Some(0x5));
+        assert_eq!(keymap.button(Keycode::W), Some(0x5));
+        assert_eq!(keymap.button(Keycode::Q), Some(0xC));
+        assert_eq!(keymap.button(Keycode::Num4), Some(0xC));
+        assert_eq!(keymap.button(Keycode::Down), None);
+    }
+
+    #[test]
+    fn keymap_files_report_the_failing_line() {
+        let path = std::env::temp_dir().join(format!("chip8-keymap-{}.txt", std::process::id()));
+        fs::write(&path, "# Arrow keys\n\nUp = 10\n").unwrap();
+        let error = Keymap::load(path.to_str().unwrap()).err().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(error.ends_with(":3: button must be a hex digit between 0 and F"));
+        assert!(Keymap::parse("Up 5").is_err());
+        assert!(Keymap::load("/nonexistent/keymap.txt").is_err());
+    }
+}
@@ -1,51 +1,358 @@
 extern crate sdl2;
 
-use std::env;
+mod cli;
+mod console;
+mod keymap;
+mod scripting;
 
 use audio::AudioBeep;
+use cheats::Cheats;
 use chip8_core::*;
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use cli::Args;
+use console::Console;
+use keymap::Keymap;
+use movie::{Movie, MoviePlayer};
+use palette::Palette;
+use recorder::FrameRecorder;
+use scheduler::Scheduler;
+use scripting::Script;
 use sdl2::keyboard::{Keycode, Mod};
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
+use sdl2::render::{Canvas, Texture};
 use sdl2::video::Window;
-use sdl2::{event::Event, pixels::Color};
+use sdl2::{
+    event::Event,
+    pixels::{Color, PixelFormatEnum},
+};
+use state::SaveState;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 
-pub const SCALE: u32 = 23;
-pub const WINDOW_WIDTH: u32 = (SCREEN_WIDTH as u32) * SCALE;
-pub const WINDOW_HEIGHT: u32 = (SCREEN_HEIGHT as u32) * SCALE;
+fn main() {
+    let mut args = Args::parse();
 
-const TICKS_PER_FRAME: u32 = 10;
+    let movie = args.play_movie.as_ref().map(|path| {
+        Movie::load(path).unwrap_or_else(|error| {
+            eprintln!("Failed to load movie {}: {}", path.display(), error);
+            std::process::exit(1);
+        })
+    });
 
-fn main() {
-    let args: Vec<_> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: chip8_emulator <rom_path>");
+    // A movie only replays correctly with the settings it was recorded with
+    if let Some(movie) = &movie {
+        args.platform = movie.platform;
+        args.timing = movie.timing;
+        args.instructions_per_second = movie.instructions_per_second;
+        args.seed = Some(movie.seed);
+        args.random = movie.random;
+        args.start_address = movie.start_address;
+        args.font = movie.font;
+        args.font_address = movie.font_address;
+
+        if args.headless {
+            args.frames = args.frames.or(Some(movie.frames));
+        }
+    }
+
+    if args.headless && args.frames.is_none() {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--headless requires --frames or --play-movie",
+            )
+            .exit();
+    }
+
+    // Prepare emulator and load ROM
+    let mut builder = Chip8::builder()
+        .platform(args.platform)
+        .random_source(args.random)
+        .start_address(args.start_address)
+        .font_set(args.font)
+        .font_address(args.font_address);
+    if let Some(path) = &args.font_file {
+        match font::Font::load(path) {
+            Ok(font) => builder = builder.font(font),
+            Err(error) => {
+                eprintln!("Failed to load font: {}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(seed) = args.seed {
+        builder = builder.seed(seed);
+    }
+    // Headless runs never open an audio device
+    if !args.mute && !args.headless {
+        builder = builder.audio(Box::new(AudioBeep::new()));
+    }
+    let mut chip = builder.build().unwrap_or_else(|error| {
+        eprintln!("Invalid machine settings: {}", error);
+        std::process::exit(1);
+    });
+    if let Err(error) = chip.load_rom(&args.rom_path) {
+        eprintln!("Failed to load ROM {}: {}", args.rom_path, error);
+        std::process::exit(1);
+    }
+
+    if let Some(movie) = &movie {
+        if movie.rom_hash != chip.rom_hash() {
+            eprintln!("The movie was recorded with a different ROM");
+            std::process::exit(1);
+        }
+    }
+    let mut player = movie.map(MoviePlayer::new);
+
+    let cheats_path = cheats_path(&args);
+    // Cheats change memory behind the inputs' back, so movies are recorded and played without them
+    let uses_movie = args.record_movie.is_some() || args.play_movie.is_some();
+    let mut cheats = if !uses_movie && (args.cheats.is_some() || cheats_path.exists()) {
+        Cheats::load(&cheats_path).unwrap_or_else(|error| {
+            eprintln!(
+                "Failed to load cheats from {}: {}",
+                cheats_path.display(),
+                error
+            );
+            std::process::exit(1);
+        })
+    } else {
+        Cheats::default()
+    };
+    if let Err(error) = cheats.apply(&mut chip) {
+        eprintln!("Invalid cheats in {}: {}", cheats_path.display(), error);
+        std::process::exit(1);
+    }
+
+    if args.record_movie.is_some() {
+        chip.start_input_recording();
+    }
+
+    // Set once the script asks to quit
+    let script_exit = match &args.script {
+        Some(path) => {
+            let script = Script::load(path, &mut chip, args.palette, args.record_scale)
+                .unwrap_or_else(|error| {
+                    eprintln!("Failed to load script {}: {}", path.display(), error);
+                    std::process::exit(1);
+                });
+            let exit = script.exit_flag();
+            chip.set_hooks(Some(Box::new(script)));
+            exit
+        }
+        None => Arc::new(AtomicBool::new(false)),
+    };
+
+    let mut recorders = create_recorders(&args).unwrap_or_else(|error| {
+        eprintln!("Failed to start recording: {}", error);
         std::process::exit(1);
+    });
+
+    if args.headless {
+        run_headless(
+            &mut chip,
+            &args,
+            &mut recorders,
+            &mut player,
+            &cheats,
+            &script_exit,
+        );
+    } else {
+        run_window(
+            &mut chip,
+            &args,
+            &mut recorders,
+            &mut player,
+            &mut cheats,
+            &script_exit,
+        );
+    }
+
+    for recorder in recorders {
+        finish_recording(recorder);
+    }
+
+    if let Some(path) = &args.record_movie {
+        save_movie(&mut chip, &args, path);
+    }
+}
+
+///
+/// Cheats were checked against the machine when they were loaded or added, so this only
+/// reports problems instead of stopping the game
+///
+fn apply_cheats(cheats: &Cheats, chip: &mut Chip8) {
+    if let Err(error) = cheats.apply(chip) {
+        eprintln!("Failed to apply cheats: {}", error);
     }
+}
+
+fn cheats_path(args: &Args) -> PathBuf {
+    args.cheats
+        .clone()
+        .unwrap_or_else(|| Path::new(&args.rom_path).with_extension("cht"))
+}
+
+fn save_movie(chip: &mut Chip8, args: &Args, path: &Path) {
+    let movie = Movie {
+        rom_hash: chip.rom_hash(),
+        seed: chip.rng_seed(),
+        random: chip.random_source(),
+        platform: args.platform,
+        timing: args.timing,
+        instructions_per_second: args.instructions_per_second,
+        start_address: args.start_address,
+        font: args.font,
+        font_address: args.font_address,
+        frames: chip.frame_count(),
+        events: chip.stop_input_recording(),
+    };
+
+    match movie.save(path) {
+        Ok(()) => eprintln!("Saved movie to {}", path.display()),
+        Err(error) => eprintln!("Failed to save movie to {}: {}", path.display(), error),
+    }
+}
+
+fn create_recorders(args: &Args) -> std::io::Result<Vec<Box<dyn FrameRecorder>>> {
+    let mut recorders = Vec::new();
+
+    if let Some(path) = &args.record_gif {
+        recorders.push(recorder::create_gif(
+            path,
+            args.record_scale,
+            &args.palette,
+        )?);
+    }
+
+    if let Some(path) = &args.record_raw {
+        recorders.push(recorder::create_raw(
+            path,
+            args.record_scale,
+            &args.palette,
+        )?);
+    }
+
+    if let Some(path) = &args.record_wav {
+        recorders.push(Box::new(recorder::WavRecorder::create(path)?));
+    }
+
+    Ok(recorders)
+}
+
+fn record_frame(recorders: &mut [Box<dyn FrameRecorder>], chip: &Chip8) {
+    for recorder in recorders {
+        if let Err(error) = recorder.record(chip) {
+            eprintln!("Failed to record frame: {}", error);
+        }
+    }
+}
+
+fn finish_recording(recorder: Box<dyn FrameRecorder>) {
+    if let Err(error) = recorder.finish() {
+        eprintln!("Failed to finish recording: {}", error);
+    }
+}
+
+///
+/// Runs the configured number of frames without a window and prints the final screen
+///
+fn run_headless(
+    chip: &mut Chip8,
+    args: &Args,
+    recorders: &mut [Box<dyn FrameRecorder>],
+    player: &mut Option<MoviePlayer>,
+    cheats: &Cheats,
+    script_exit: &AtomicBool,
+) {
+    let mut scheduler = Scheduler::new(args.instructions_per_second);
+    scheduler.set_timing(args.timing);
+    for _ in 0..args.frames.unwrap_or(0) {
+        if let Some(player) = player {
+            player.apply_inputs(chip);
+        }
+
+        apply_cheats(cheats, chip);
+        scheduler.run_frame(chip);
+        record_frame(recorders, chip);
+
+        if script_exit.load(Ordering::Relaxed) {
+            break;
+        }
+    }
+
+    // Raw frames piped to stdout mustn't be mixed with text
+    if args.record_raw.as_deref() == Some(Path::new("-")) {
+        return;
+    }
+
+    let display = chip.display();
+    for y in 0..display.height() {
+        let line: String = (0..display.width())
+            .map(|x| if display.pixel(x, y) { '#' } else { '.' })
+            .collect();
+        println!("{}", line);
+    }
+}
+
+fn run_window(
+    chip: &mut Chip8,
+    args: &Args,
+    recorders: &mut [Box<dyn FrameRecorder>],
+    player: &mut Option<MoviePlayer>,
+    cheats: &mut Cheats,
+    script_exit: &AtomicBool,
+) {
+    let keymap = match &args.keymap {
+        Some(path) => Keymap::load(path).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }),
+        None => Keymap::default(),
+    };
 
-    let rom_path = &args[1];
+    let window_width = (SCREEN_WIDTH as u32) * args.scale;
+    let window_height = (SCREEN_HEIGHT as u32) * args.scale;
 
     // Setup SDL
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("Chip-8 Emulator", WINDOW_WIDTH, WINDOW_HEIGHT)
-        .position_centered()
-        .opengl()
-        .build()
-        .unwrap();
+    let mut window_builder = video_subsystem.window("Chip-8 Emulator", window_width, window_height);
+    window_builder.position_centered().opengl();
+    if args.fullscreen {
+        window_builder.fullscreen_desktop();
+    }
+    let window = window_builder.build().unwrap();
     let mut canvas = window.into_canvas().present_vsync().build().unwrap();
-
-    // Setup audio
-    let audio = AudioBeep::new();
-
-    // Prepare emulator and load ROM
-    let mut chip = Chip8::new(audio);
-    chip.load_rom(rom_path);
+    // Let SDL scale the picture when the window size doesn't match, e.g. in fullscreen
+    canvas
+        .set_logical_size(window_width, window_height)
+        .unwrap();
+    // The screen is drawn as a single native resolution texture, stretched to the window
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(
+            PixelFormatEnum::RGB24,
+            SCREEN_WIDTH as u32,
+            SCREEN_HEIGHT as u32,
+        )
+        .unwrap();
+    let mut texture_pixels = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 3];
 
     // Run emulator loop
     let mut event_pump = sdl_context.event_pump().unwrap();
-    let mut pause_emulator = false;
+    let mut pause_emulator = args.paused;
+    let mut slow_motion = false;
+    let mut turbo = false;
+    let mut frames_run: u64 = 0;
+
+    let mut scheduler = Scheduler::new(args.instructions_per_second);
+    scheduler.set_timing(args.timing);
+    let mut last_update = Instant::now();
+    // GIF recording toggled with F9, separate from the recordings requested on the command line
+    let mut hotkey_recording: Option<Box<dyn FrameRecorder>> = None;
+    let mut console = args.console.then(|| Console::spawn(cheats_path(args)));
 
     'emulator_loop: loop {
         for event in event_pump.poll_iter() {
@@ -60,10 +367,16 @@ fn main() {
                 Event::KeyDown {
                     keymod,
                     keycode: Some(Keycode::Backspace),
+                    repeat: false,
                     ..
-                } => {
-                    if keymod == Mod::LCTRLMOD || keymod == Mod::RCTRLMOD {
-                        chip.reset();
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    // Resets are part of the movie being played, like its key presses
+                    if player.is_some() {
+                        eprintln!("The machine can't be reset while a movie is played");
+                    } else if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        chip.hard_reset();
+                    } else {
+                        chip.soft_reset();
                     }
                 }
                 Event::KeyDown {
@@ -73,16 +386,92 @@ fn main() {
                     pause_emulator = !pause_emulator;
                 }
                 Event::KeyDown {
-                    keycode: Some(key), ..
+                    keymod,
+                    keycode: Some(Keycode::F12),
+                    repeat: false,
+                    ..
+                } => {
+                    // Shift saves at the native 64x32 resolution instead of the window's scale
+                    let native = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    take_screenshot(chip, args, if native { 1 } else { args.scale });
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    repeat: false,
+                    ..
+                } => {
+                    save_state(chip, args);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    repeat: false,
+                    ..
+                } => {
+                    // Jumping to another state would break the movie being recorded or played
+                    if args.record_movie.is_some() || player.is_some() {
+                        eprintln!("States can't be loaded while a movie is recorded or played");
+                    } else {
+                        load_state(chip, args);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    repeat: false,
+                    ..
+                } => match hotkey_recording.take() {
+                    Some(recorder) => {
+                        finish_recording(recorder);
+                        eprintln!("Stopped recording");
+                    }
+                    None => {
+                        let path = args
+                            .capture_dir
+                            .join(screenshot::timestamped_file_name("chip8", "gif"));
+                        match recorder::create_gif(&path, args.record_scale, &args.palette) {
+                            Ok(recorder) => {
+                                eprintln!("Recording to {}", path.display());
+                                hotkey_recording = Some(recorder);
+                            }
+                            Err(error) => {
+                                eprintln!("Failed to record to {}: {}", path.display(), error)
+                            }
+                        }
+                    }
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    repeat: false,
+                    ..
+                } => {
+                    slow_motion = !slow_motion;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => {
+                    turbo = true;
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::Tab),
+                    ..
                 } => {
-                    if let Some(button) = key_to_button(key) {
+                    turbo = false;
+                }
+                // The keypad is driven by the movie until it ends. Key repeats would log
+                // duplicate presses to the movie being recorded
+                Event::KeyDown {
+                    keycode: Some(key),
+                    repeat: false,
+                    ..
+                } if player.is_none() => {
+                    if let Some(button) = keymap.button(key) {
                         chip.key_press(button, true);
                     }
                 }
                 Event::KeyUp {
                     keycode: Some(key), ..
-                } => {
-                    if let Some(button) = key_to_button(key) {
+                } if player.is_none() => {
+                    if let Some(button) = keymap.button(key) {
                         chip.key_press(button, false);
                     }
                 }
@@ -90,72 +479,141 @@ fn main() {
             }
         }
 
-        if pause_emulator {
-            continue;
+        if let Some(console) = console.as_mut() {
+            console.poll(chip, cheats);
+        }
+
+        if script_exit.load(Ordering::Relaxed)
+            || args.frames.is_some_and(|frames| frames_run >= frames)
+        {
+            break 'emulator_loop;
         }
 
-        // Perform some work cycles
-        for _ in 0..TICKS_PER_FRAME {
-            chip.tick();
+        // Emulation follows the wall clock, the display's refresh rate only limits how often we render
+        let now = Instant::now();
+        let elapsed = now - last_update;
+        last_update = now;
+
+        if !pause_emulator {
+            let speed = match (turbo, slow_motion) {
+                (true, _) => args.turbo_speed,
+                (false, true) => args.slow_speed,
+                (false, false) => 1.0,
+            };
+            scheduler.set_speed(speed);
+
+            if let Some(movie_player) = player.as_mut() {
+                movie_player.apply_inputs(chip);
+            }
+
+            frames_run += scheduler.run_with(chip, elapsed, |chip| {
+                record_frame(recorders, chip);
+                record_frame(hotkey_recording.as_mut_slice(), chip);
+                apply_cheats(cheats, chip);
+
+                if let Some(movie_player) = player.as_mut() {
+                    movie_player.apply_inputs(chip);
+                }
+            }) as u64;
+
+            if player
+                .as_ref()
+                .is_some_and(|player| player.is_finished(chip))
+            {
+                eprintln!("Movie finished");
+                *player = None;
+            }
         }
 
-        chip.tick_timers();
-        render(&chip, &mut canvas);
+        render(
+            chip,
+            &mut canvas,
+            &mut texture,
+            &mut texture_pixels,
+            &args.palette,
+        );
+    }
+
+    if let Some(recorder) = hotkey_recording {
+        finish_recording(recorder);
     }
 }
 
-fn render(chip: &Chip8, canvas: &mut Canvas<Window>) {
-    // Clear canvas as green
-    canvas.set_draw_color(Color::RGB(6, 138, 41));
-    canvas.clear();
+///
+/// Save states are kept per ROM, named after the ROM file
+///
+fn state_path(args: &Args) -> PathBuf {
+    let name = Path::new(&args.rom_path)
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    args.capture_dir.join(format!("{}.state", name))
+}
 
-    let screen_buffer = chip.screen;
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
+fn save_state(chip: &Chip8, args: &Args) {
+    let path = state_path(args);
+    match chip.save_state().save(&path) {
+        Ok(()) => eprintln!("Saved state to {}", path.display()),
+        Err(error) => eprintln!("Failed to save state to {}: {}", path.display(), error),
+    }
+}
 
-    for (i, pixel) in screen_buffer.iter().enumerate() {
-        if *pixel {
-            // Convert our 1D array's index into a 2D (x,y) position
-            let x = (i % SCREEN_WIDTH) as u32;
-            let y = (i / SCREEN_WIDTH) as u32;
+fn load_state(chip: &mut Chip8, args: &Args) {
+    let path = state_path(args);
+    let result = SaveState::load(&path)
+        .map_err(|error| error.to_string())
+        .and_then(|state| chip.load_state(&state));
 
-            let rect = Rect::new((x * SCALE) as i32, (y * SCALE) as i32, SCALE, SCALE);
-            canvas.fill_rect(rect).unwrap();
-        }
+    match result {
+        Ok(()) => eprintln!("Loaded state from {}", path.display()),
+        Err(error) => eprintln!("Failed to load state from {}: {}", path.display(), error),
     }
+}
 
-    canvas.present();
+fn take_screenshot(chip: &Chip8, args: &Args, scale: u32) {
+    let path = args
+        .capture_dir
+        .join(screenshot::timestamped_file_name("chip8", "png"));
+
+    match screenshot::save_png(chip, scale, &args.palette, &path) {
+        Ok(()) => eprintln!("Saved screenshot to {}", path.display()),
+        Err(error) => eprintln!("Failed to save screenshot to {}: {}", path.display(), error),
+    }
 }
 
-/*
-    Keyboard                    Chip-8
-    +---+---+---+---+           +---+---+---+---+
-    | 1 | 2 | 3 | 4 |           | 1 | 2 | 3 | C |
-    +---+---+---+---+           +---+---+---+---+
-    | Q | W | E | R |           | 4 | 5 | 6 | D |
-    +---+---+---+---+     =>    +---+---+---+---+
-    | A | S | D | F |           | 7 | 8 | 9 | E |
-    +---+---+---+---+           +---+---+---+---+
-    | Z | X | C | V |           | A | 0 | B | F |
-    +---+---+---+---+           +---+---+---+---+
-*/
-fn key_to_button(key: Keycode) -> Option<usize> {
-    match key {
-        Keycode::Num1 => Some(0x1),
-        Keycode::Num2 => Some(0x2),
-        Keycode::Num3 => Some(0x3),
-        Keycode::Num4 => Some(0xC),
-        Keycode::Q => Some(0x4),
-        Keycode::W => Some(0x5),
-        Keycode::E => Some(0x6),
-        Keycode::R => Some(0xD),
-        Keycode::A => Some(0x7),
-        Keycode::S => Some(0x8),
-        Keycode::D => Some(0x9),
-        Keycode::F => Some(0xE),
-        Keycode::Z => Some(0xA),
-        Keycode::X => Some(0x0),
-        Keycode::C => Some(0xB),
-        Keycode::V => Some(0xF),
-        _ => None,
+///
+/// Copies the screen rows that changed into the streaming texture and presents it.
+/// `pixels` holds the texture's RGB contents between frames
+///
+fn render(
+    chip: &mut Chip8,
+    canvas: &mut Canvas<Window>,
+    texture: &mut Texture,
+    pixels: &mut [u8],
+    palette: &Palette,
+) {
+    let dirty_rows = chip.take_dirty_rows();
+    if dirty_rows != 0 {
+        let display = chip.display();
+        let pitch = display.width() * 3;
+
+        for (y, line) in pixels.chunks_exact_mut(pitch).enumerate() {
+            if dirty_rows & (1 << y) == 0 {
+                continue;
+            }
+            for (x, rgb) in line.chunks_exact_mut(3).enumerate() {
+                let (r, g, b) = palette.color(display.pixel(x, y));
+                rgb.copy_from_slice(&[r, g, b]);
+            }
+        }
+
+        texture.update(None, pixels, pitch).unwrap();
     }
+
+    // Presenting every frame keeps the loop in step with the display's refresh rate.
+    // Clearing fills the letterbox bars in fullscreen
+    canvas.set_draw_color(Color::from(palette.background));
+    canvas.clear();
+    canvas.copy(texture, None, None).unwrap();
+    canvas.present();
 }
@@ -0,0 +1,314 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use chip8_core::{hooks::Hooks, palette::Palette, screenshot, state::SaveState, Chip8};
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, AST, INT};
+
+///
+/// State shared between the script's functions and the hooks calling into the script
+///
+struct Shared {
+    // The machine is swapped in here while script code runs and swapped back out afterwards
+    machine: Chip8,
+    frame_callbacks: Vec<FnPtr>,
+    pc_callbacks: HashMap<u16, Vec<FnPtr>>,
+    write_callbacks: HashMap<u16, Vec<FnPtr>>,
+}
+
+type SharedState = Arc<Mutex<Shared>>;
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+///
+/// A Rhai script driving the machine. The script's top level runs once when it is loaded
+/// and registers callbacks with `on_frame`, `on_pc` and `on_write`.
+///
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    shared: SharedState,
+    exit: Arc<AtomicBool>,
+}
+
+impl Script {
+    pub fn load(
+        path: &Path,
+        chip: &mut Chip8,
+        palette: Palette,
+        scale: u32,
+    ) -> Result<Self, String> {
+        let shared = Arc::new(Mutex::new(Shared {
+            machine: Chip8::new(None),
+            frame_callbacks: Vec::new(),
+            pc_callbacks: HashMap::new(),
+            write_callbacks: HashMap::new(),
+        }));
+        let exit = Arc::new(AtomicBool::new(false));
+
+        let mut engine = Engine::new();
+        // stdout is reserved for the headless screen dump and raw frames
+        engine.on_print(|text| eprintln!("{}", text));
+        register_machine_api(&mut engine, &shared, palette, scale);
+        register_callback_api(&mut engine, &shared);
+
+        let exit_flag = exit.clone();
+        engine.register_fn("exit", move || exit_flag.store(true, Ordering::Relaxed));
+
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|error| error.to_string())?;
+
+        let script = Script {
+            engine,
+            ast,
+            shared,
+            exit,
+        };
+
+        script.swap_machine(chip);
+        let result = script.engine.run_ast(&script.ast);
+        script.swap_machine(chip);
+        result.map_err(|error| error.to_string())?;
+
+        Ok(script)
+    }
+
+    ///
+    /// Set once the script has called `exit()`
+    ///
+    pub fn exit_flag(&self) -> Arc<AtomicBool> {
+        self.exit.clone()
+    }
+
+    fn swap_machine(&self, chip: &mut Chip8) {
+        std::mem::swap(chip, &mut self.shared.lock().unwrap().machine);
+    }
+
+    fn run_callbacks(&self, chip: &mut Chip8, callbacks: Vec<FnPtr>, args: Vec<Dynamic>) {
+        self.swap_machine(chip);
+        for callback in callbacks {
+            if let Err(error) = callback.call::<Dynamic>(&self.engine, &self.ast, args.clone()) {
+                eprintln!("Script error: {}", error);
+            }
+        }
+        self.swap_machine(chip);
+    }
+}
+
+impl Hooks for Script {
+    fn before_instruction(&mut self, chip: &mut Chip8) {
+        let pc = chip.program_counter();
+        let callbacks = self.shared.lock().unwrap().pc_callbacks.get(&pc).cloned();
+
+        if let Some(callbacks) = callbacks {
+            self.run_callbacks(chip, callbacks, vec![Dynamic::from(pc as INT)]);
+        }
+    }
+
+    fn memory_written(&mut self, chip: &mut Chip8, address: u16, value: u8) {
+        let callbacks = self
+            .shared
+            .lock()
+            .unwrap()
+            .write_callbacks
+            .get(&address)
+            .cloned();
+
+        if let Some(callbacks) = callbacks {
+            let args = vec![Dynamic::from(address as INT), Dynamic::from(value as INT)];
+            self.run_callbacks(chip, callbacks, args);
+        }
+    }
+
+    fn frame_end(&mut self, chip: &mut Chip8) {
+        let callbacks = self.shared.lock().unwrap().frame_callbacks.clone();
+
+        if !callbacks.is_empty() {
+            let frame = chip.frame_count() as INT;
+            self.run_callbacks(chip, callbacks, vec![Dynamic::from(frame)]);
+        }
+    }
+}
+
+fn with_machine<T>(
+    shared: &SharedState,
+    f: impl FnOnce(&mut Chip8) -> Result<T, String>,
+) -> ScriptResult<T> {
+    f(&mut shared.lock().unwrap().machine).map_err(Into::into)
+}
+
+fn checked<T: TryFrom<INT>>(value: INT, what: &str) -> Result<T, String> {
+    T::try_from(value).map_err(|_| format!("{} {} is out of range", what, value))
+}
+
+fn register(x: INT) -> Result<usize, String> {
+    checked::<usize>(x, "register").and_then(|x| match x {
+        0..=15 => Ok(x),
+        _ => Err(format!("register {} is out of range", x)),
+    })
+}
+
+fn button(button: INT) -> Result<usize, String> {
+    checked::<usize>(button, "button").and_then(|button| match button {
+        0..=15 => Ok(button),
+        _ => Err(format!("button {} is out of range", button)),
+    })
+}
+
+///
+/// Functions reading and changing the machine:
+/// `v(x)`, `set_v(x, value)`, `reg_i()`, `set_reg_i(value)`, `pc()`, `set_pc(value)`,
+/// `peek(address)`, `poke(address, value)`, `press(button)`, `release(button)`, `frame()`,
+/// `screenshot(path)`, `save_state(path)` and `load_state(path)`
+///
+fn register_machine_api(engine: &mut Engine, shared: &SharedState, palette: Palette, scale: u32) {
+    let s = shared.clone();
+    engine.register_fn("v", move |x: INT| -> ScriptResult<INT> {
+        with_machine(&s, |chip| Ok(chip.v_registers()[register(x)?] as INT))
+    });
+
+    let s = shared.clone();
+    engine.register_fn("set_v", move |x: INT, value: INT| -> ScriptResult<()> {
+        with_machine(&s, |chip| {
+            chip.set_v_register(register(x)?, checked(value, "value")?)
+        })
+    });
+
+    let s = shared.clone();
+    engine.register_fn("reg_i", move || -> ScriptResult<INT> {
+        with_machine(&s, |chip| Ok(chip.i_register() as INT))
+    });
+
+    let s = shared.clone();
+    engine.register_fn("set_reg_i", move |value: INT| -> ScriptResult<()> {
+        with_machine(&s, |chip| {
+            chip.set_i_register(checked(value, "value")?);
+            Ok(())
+        })
+    });
+
+    let s = shared.clone();
+    engine.register_fn("pc", move || -> ScriptResult<INT> {
+        with_machine(&s, |chip| Ok(chip.program_counter() as INT))
+    });
+
+    let s = shared.clone();
+    engine.register_fn("set_pc", move |value: INT| -> ScriptResult<()> {
+        with_machine(&s, |chip| {
+            chip.set_program_counter(checked(value, "address")?);
+            Ok(())
+        })
+    });
+
+    let s = shared.clone();
+    engine.register_fn("peek", move |address: INT| -> ScriptResult<INT> {
+        with_machine(&s, |chip| {
+            let address: usize = checked(address, "address")?;
+            chip.memory()
+                .get(address)
+                .map(|&value| value as INT)
+                .ok_or_else(|| format!("address {:#x} is out of range", address))
+        })
+    });
+
+    let s = shared.clone();
+    engine.register_fn(
+        "poke",
+        move |address: INT, value: INT| -> ScriptResult<()> {
+            with_machine(&s, |chip| {
+                chip.write_memory(checked(address, "address")?, &[checked(value, "value")?])
+            })
+        },
+    );
+
+    let s = shared.clone();
+    engine.register_fn("press", move |index: INT| -> ScriptResult<()> {
+        with_machine(&s, |chip| {
+            chip.key_press(button(index)?, true);
+            Ok(())
+        })
+    });
+
+    let s = shared.clone();
+    engine.register_fn("release", move |index: INT| -> ScriptResult<()> {
+        with_machine(&s, |chip| {
+            chip.key_press(button(index)?, false);
+            Ok(())
+        })
+    });
+
+    let s = shared.clone();
+    engine.register_fn("frame", move || -> ScriptResult<INT> {
+        with_machine(&s, |chip| Ok(chip.frame_count() as INT))
+    });
+
+    let s = shared.clone();
+    engine.register_fn("screenshot", move |path: &str| -> ScriptResult<()> {
+        with_machine(&s, |chip| {
+            screenshot::save_png(chip, scale, &palette, path).map_err(|error| error.to_string())
+        })
+    });
+
+    let s = shared.clone();
+    engine.register_fn("save_state", move |path: &str| -> ScriptResult<()> {
+        with_machine(&s, |chip| {
+            chip.save_state()
+                .save(path)
+                .map_err(|error| error.to_string())
+        })
+    });
+
+    let s = shared.clone();
+    engine.register_fn("load_state", move |path: &str| -> ScriptResult<()> {
+        with_machine(&s, |chip| {
+            let state = SaveState::load(path).map_err(|error| error.to_string())?;
+            chip.load_state(&state)
+        })
+    });
+}
+
+///
+/// Functions registering callbacks: `on_frame(fn(frame))`, `on_pc(address, fn(pc))`
+/// and `on_write(address, fn(address, value))`
+///
+fn register_callback_api(engine: &mut Engine, shared: &SharedState) {
+    let s = shared.clone();
+    engine.register_fn("on_frame", move |callback: FnPtr| {
+        s.lock().unwrap().frame_callbacks.push(callback);
+    });
+
+    let s = shared.clone();
+    engine.register_fn(
+        "on_pc",
+        move |address: INT, callback: FnPtr| -> ScriptResult<()> {
+            let address = checked(address, "address")?;
+            s.lock()
+                .unwrap()
+                .pc_callbacks
+                .entry(address)
+                .or_default()
+                .push(callback);
+            Ok(())
+        },
+    );
+
+    let s = shared.clone();
+    engine.register_fn(
+        "on_write",
+        move |address: INT, callback: FnPtr| -> ScriptResult<()> {
+            let address = checked(address, "address")?;
+            s.lock()
+                .unwrap()
+                .write_callbacks
+                .entry(address)
+                .or_default()
+                .push(callback);
+            Ok(())
+        },
+    );
+}
@@ -0,0 +1,419 @@
+//!
+//! Differential tests: random instruction sequences run on the emulator and on `Model`,
+//! a deliberately simple interpreter written straight from the instruction set reference,
+//! with the full machine state compared after every step. Failing programs are shrunk
+//! to a minimal repro by proptest.
+//!
+//! `CXNN` can't be predicted, the model only checks that the random byte was masked
+//! and takes the value over from the emulator.
+//!
+use chip8_core::{
+    quirks::{Platform, Quirks},
+    state::SaveState,
+    Chip8, RAM_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH, START_ADDRESS,
+};
+use proptest::prelude::*;
+
+const STACK_DEPTH: usize = 16;
+
+/// One thing happening to the machine between state comparisons
+#[derive(Clone, Debug)]
+enum Step {
+    /// Fetches and executes the instruction, which is written to RAM at PC first
+    Instruction(u16),
+    /// A 60 Hz timer tick
+    Timers,
+    Key {
+        button: usize,
+        pressed: bool,
+    },
+}
+
+///
+/// The specification: every instruction written out as plainly as possible, sharing
+/// no code with the emulator
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Model {
+    pc: u16,
+    i: u16,
+    v: [u8; 16],
+    stack: [u16; STACK_DEPTH],
+    sp: usize,
+    ram: Vec<u8>,
+    delay: u8,
+    sound: u8,
+    keys: [bool; 16],
+    screen: Vec<bool>,
+}
+
+impl Model {
+    fn from_state(state: &SaveState) -> Self {
+        Model {
+            pc: state.program_counter,
+            i: state.i_register,
+            v: state.v_registers,
+            stack: state.stack.clone().try_into().unwrap(),
+            sp: state.stack_pointer as usize,
+            ram: state.ram.clone(),
+            delay: state.delay_timer,
+            sound: state.sound_timer,
+            keys: state.keyboard,
+            screen: state.screen.clone(),
+        }
+    }
+
+    fn read(&self, address: usize) -> u8 {
+        self.ram[address % RAM_SIZE]
+    }
+
+    fn write(&mut self, address: usize, value: u8) {
+        self.ram[address % RAM_SIZE] = value;
+    }
+
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    fn step(&mut self, step: &Step, quirks: Quirks) {
+        match *step {
+            Step::Instruction(op) => {
+                self.write(self.pc as usize, (op >> 8) as u8);
+                self.write(self.pc as usize + 1, op as u8);
+                self.pc = self.pc.wrapping_add(2);
+                self.execute(op, quirks);
+            }
+            Step::Timers => {
+                self.delay = self.delay.saturating_sub(1);
+                self.sound = self.sound.saturating_sub(1);
+            }
+            Step::Key { button, pressed } => self.keys[button] = pressed,
+        }
+    }
+
+    fn execute(&mut self, op: u16, quirks: Quirks) {
+        let x = (op >> 8 & 0xF) as usize;
+        let y = (op >> 4 & 0xF) as usize;
+        let n = op & 0xF;
+        let nn = op as u8;
+        let nnn = op & 0xFFF;
+        let (vx, vy) = (self.v[x], self.v[y]);
+
+        match op >> 12 {
+            0x0 if op == 0x00E0 => self.screen.fill(false),
+            // Returning with an empty stack does nothing
+            0x0 if op == 0x00EE && self.sp > 0 => {
+                self.sp -= 1;
+                self.pc = self.stack[self.sp];
+            }
+            0x1 => self.pc = nnn,
+            0x2 => {
+                // A full stack drops its oldest return address
+                if self.sp == STACK_DEPTH {
+                    self.stack.copy_within(1.., 0);
+                    self.sp -= 1;
+                }
+                self.stack[self.sp] = self.pc;
+                self.sp += 1;
+                self.pc = nnn;
+            }
+            0x3 => self.skip_if(vx == nn),
+            0x4 => self.skip_if(vx != nn),
+            0x5 if n == 0 => self.skip_if(vx == vy),
+            0x6 => self.v[x] = nn,
+            0x7 => self.v[x] = vx.wrapping_add(nn),
+            0x8 => self.alu(x, vx, vy, n, quirks),
+            0x9 if n == 0 => self.skip_if(vx != vy),
+            0xA => self.i = nnn,
+            0xB => self.pc = nnn + self.v[0] as u16,
+            0xD => self.draw(vx as usize, vy as usize, n as usize, quirks),
+            0xE if nn == 0x9E => self.skip_if(self.keys[vx as usize % 16]),
+            0xE if nn == 0xA1 => self.skip_if(!self.keys[vx as usize % 16]),
+            0xF => match nn {
+                0x07 => self.v[x] = self.delay,
+                0x0A => match self.keys.iter().position(|&key| key) {
+                    Some(key) => self.v[x] = key as u8,
+                    None => self.pc = self.pc.wrapping_sub(2),
+                },
+                0x15 => self.delay = vx,
+                0x18 => self.sound = vx,
+                0x1E => self.i = self.i.wrapping_add(vx as u16),
+                0x29 => self.i = (vx as u16 & 0xF) * 5,
+                // The default COSMAC VIP font has no large digits
+                0x30 => (),
+                0x33 => {
+                    self.write(self.i as usize, vx / 100);
+                    self.write(self.i as usize + 1, vx / 10 % 10);
+                    self.write(self.i as usize + 2, vx % 10);
+                }
+                0x55 => {
+                    for r in 0..=x {
+                        self.write(self.i as usize + r, self.v[r]);
+                    }
+                    if quirks.memory_increment {
+                        self.i = self.i.wrapping_add(x as u16 + 1);
+                    }
+                }
+                0x65 => {
+                    for r in 0..=x {
+                        self.v[r] = self.read(self.i as usize + r);
+                    }
+                    if quirks.memory_increment {
+                        self.i = self.i.wrapping_add(x as u16 + 1);
+                    }
+                }
+                _ => (),
+            },
+            // Everything else, including machine code routines, is ignored
+            _ => (),
+        }
+    }
+
+    fn alu(&mut self, x: usize, vx: u8, vy: u8, n: u16, quirks: Quirks) {
+        let shifted = if quirks.shift_uses_vy { vy } else { vx };
+        let (result, flag) = match n {
+            0x0 => (vy, None),
+            0x1 => (vx | vy, quirks.vf_reset.then_some(0)),
+            0x2 => (vx & vy, quirks.vf_reset.then_some(0)),
+            0x3 => (vx ^ vy, quirks.vf_reset.then_some(0)),
+            0x4 => (
+                vx.wrapping_add(vy),
+                Some((vx as u16 + vy as u16 > 0xFF) as u8),
+            ),
+            0x5 => (vx.wrapping_sub(vy), Some((vx >= vy) as u8)),
+            0x6 => (shifted >> 1, Some(shifted & 1)),
+            0x7 => (vy.wrapping_sub(vx), Some((vy >= vx) as u8)),
+            0xE => (shifted << 1, Some(shifted >> 7)),
+            _ => return,
+        };
+
+        // The flag is written last, so it wins when VF is the destination
+        self.v[x] = result;
+        if let Some(flag) = flag {
+            self.v[0xF] = flag;
+        }
+    }
+
+    fn draw(&mut self, x: usize, y: usize, rows: usize, quirks: Quirks) {
+        let mut collision = false;
+        for row in 0..rows {
+            let bits = self.read(self.i as usize + row);
+            for column in 0..8 {
+                if bits & (0x80 >> column) == 0 {
+                    continue;
+                }
+
+                let px = x % SCREEN_WIDTH + column;
+                let py = y % SCREEN_HEIGHT + row;
+                if quirks.clip_sprites && (px >= SCREEN_WIDTH || py >= SCREEN_HEIGHT) {
+                    continue;
+                }
+
+                let pixel = &mut self.screen[py % SCREEN_HEIGHT * SCREEN_WIDTH + px % SCREEN_WIDTH];
+                collision |= *pixel;
+                *pixel = !*pixel;
+            }
+        }
+        self.v[0xF] = collision as u8;
+    }
+}
+
+///
+/// Describes every part of the machine state where the emulator and the model disagree
+///
+fn differences(chip: &SaveState, model: &Model) -> Vec<String> {
+    let actual = Model::from_state(chip);
+    let mut differences = Vec::new();
+
+    macro_rules! compare {
+        ($($field:ident),*) => {
+            $(if actual.$field != model.$field {
+                differences.push(format!(
+                    "{}: emulator {:X?}, model {:X?}",
+                    stringify!($field),
+                    actual.$field,
+                    model.$field
+                ));
+            })*
+        };
+    }
+    compare!(pc, i, v, stack, sp, delay, sound, keys);
+
+    for (address, (actual, expected)) in actual.ram.iter().zip(&model.ram).enumerate() {
+        if actual != expected {
+            differences.push(format!(
+                "ram[{:#05X}]: emulator {:#04X}, model {:#04X}",
+                address, actual, expected
+            ));
+        }
+    }
+    if actual.screen != model.screen {
+        differences.push("screen".to_string());
+    }
+
+    differences
+}
+
+fn instruction() -> impl Strategy<Value = u16> {
+    // Instruction patterns as (fixed bits, random bits), so most of the generated
+    // instructions are valid ones rather than ignored noise
+    const PATTERNS: &[(u16, u16)] = &[
+        (0x00E0, 0x0000),
+        (0x00EE, 0x0000),
+        (0x1000, 0x0FFF),
+        (0x2000, 0x0FFF),
+        (0x3000, 0x0FFF),
+        (0x4000, 0x0FFF),
+        (0x5000, 0x0FF0),
+        (0x6000, 0x0FFF),
+        (0x7000, 0x0FFF),
+        (0x8000, 0x0FF7),
+        (0x800E, 0x0FF0),
+        (0x9000, 0x0FF0),
+        (0xA000, 0x0FFF),
+        (0xB000, 0x0FFF),
+        (0xC000, 0x0FFF),
+        (0xD000, 0x0FFF),
+        (0xE09E, 0x0F00),
+        (0xE0A1, 0x0F00),
+        (0xF007, 0x0F00),
+        (0xF00A, 0x0F00),
+        (0xF015, 0x0F00),
+        (0xF018, 0x0F00),
+        (0xF01E, 0x0F00),
+        (0xF029, 0x0F00),
+        (0xF030, 0x0F00),
+        (0xF033, 0x0F00),
+        (0xF055, 0x0F00),
+        (0xF065, 0x0F00),
+    ];
+
+    prop_oneof![
+        9 => (prop::sample::select(PATTERNS), any::<u16>())
+            .prop_map(|((fixed, random), bits)| fixed | (bits & random)),
+        1 => any::<u16>(),
+    ]
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    prop_oneof![
+        8 => instruction().prop_map(Step::Instruction),
+        1 => Just(Step::Timers),
+        1 => (0..16usize, any::<bool>()).prop_map(|(button, pressed)| Step::Key { button, pressed }),
+    ]
+}
+
+///
+/// Registers, timers and program bytes to start from. Kept small instead of a whole
+/// `SaveState` so that failing cases print readably
+///
+#[derive(Clone, Debug)]
+struct Initial {
+    v_registers: [u8; 16],
+    i_register: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+    rom: Vec<u8>,
+}
+
+impl Initial {
+    fn state(&self) -> SaveState {
+        let mut chip = Chip8::new(None);
+        chip.load_rom_bytes(&self.rom);
+
+        let mut state = chip.save_state();
+        state.v_registers = self.v_registers;
+        state.i_register = self.i_register;
+        state.delay_timer = self.delay_timer;
+        state.sound_timer = self.sound_timer;
+        state
+    }
+}
+
+fn initial() -> impl Strategy<Value = Initial> {
+    (
+        any::<[u8; 16]>(),
+        0..RAM_SIZE as u16,
+        any::<(u8, u8)>(),
+        prop::collection::vec(any::<u8>(), 0..256),
+    )
+        .prop_map(
+            |(v_registers, i_register, (delay_timer, sound_timer), rom)| Initial {
+                v_registers,
+                i_register,
+                delay_timer,
+                sound_timer,
+                rom,
+            },
+        )
+}
+
+fn run(platform: Platform, initial: &Initial, steps: &[Step]) -> Result<(), TestCaseError> {
+    let quirks = platform.quirks();
+    let initial = initial.state();
+    let mut chip = Chip8::new(None);
+    chip.load_state(&initial).unwrap();
+    chip.set_quirks(quirks);
+    let mut model = Model::from_state(&initial);
+
+    for (index, step) in steps.iter().enumerate() {
+        match *step {
+            Step::Instruction(op) => {
+                let pc = chip.program_counter() as usize;
+                chip.write_memory((pc % RAM_SIZE) as u16, &[(op >> 8) as u8])
+                    .unwrap();
+                chip.write_memory(((pc + 1) % RAM_SIZE) as u16, &[op as u8])
+                    .unwrap();
+                chip.tick();
+            }
+            Step::Timers => chip.tick_timers(),
+            Step::Key { button, pressed } => chip.key_press(button, pressed),
+        }
+        model.step(step, quirks);
+
+        if let Step::Instruction(op) = *step {
+            if op >> 12 == 0xC {
+                let x = (op >> 8 & 0xF) as usize;
+                let random = chip.v_registers()[x];
+                prop_assert_eq!(random & !(op as u8), 0, "CXNN didn't mask the random byte");
+                model.v[x] = random;
+            }
+        }
+
+        let differences = differences(&chip.save_state(), &model);
+        prop_assert!(
+            differences.is_empty(),
+            "{} differs from the model after step {} ({:X?}):\n{}",
+            platform,
+            index,
+            step,
+            differences.join("\n")
+        );
+    }
+
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn emulator_matches_the_model(
+        platform in prop::sample::select(Platform::ALL.to_vec()),
+        initial in initial(),
+        steps in prop::collection::vec(step(), 1..64),
+    ) {
+        run(platform, &initial, &steps)?;
+    }
+}
+
+#[test]
+fn model_starts_where_the_emulator_does() {
+    let chip = Chip8::new(None);
+    let model = Model::from_state(&chip.save_state());
+
+    assert_eq!(model.pc, START_ADDRESS);
+    assert!(differences(&chip.save_state(), &model).is_empty());
+}
@@ -0,0 +1,283 @@
+//!
+//! Runs test ROMs headlessly and compares the final screen against golden images in
+//! `tests/golden`, once per platform preset.
+//!
+//! The probe ROM below is built in. The ROMs of Timendus's CHIP-8 test suite aren't part
+//! of the repository, so their tests are ignored by default: download them as described in
+//! `tests/roms/README.md` and run `cargo test --test timendus -- --include-ignored`.
+//! Run with `BLESS=1` to write the golden images of the current build after checking that
+//! the screens look right. Each golden image starts with the hash of the ROM it was made
+//! from, so a ROM of another release is reported instead of failing on its screen.
+//!
+use std::{env, fs, path::PathBuf};
+
+use chip8_core::{quirks::Platform, scheduler::Scheduler, Chip8};
+
+/// Frames each ROM runs for, enough for every test to finish at the default speed
+const FRAMES: u64 = 600;
+
+/// The quirks and keypad tests skip their menu when this address holds the choice
+const MENU_CHOICE_ADDRESS: u16 = 0x1FF;
+
+/// Prints the results of the quirky and flag-setting instructions as decimal numbers, one
+/// row per check, and draws a digit across the bottom right corner of the screen
+const PROBE: &[u8] = &[
+    0x6B, 0x04, // 200: VB = 4
+    0x6C, 0x00, // 202: VC = 0
+    0x6F, 0x05, // 204: VF = 5
+    0x61, 0x03, // 206: V1 = 3
+    0x62, 0x05, // 208: V2 = 5
+    0x81, 0x21, // 20A: V1 |= V2, VF reset quirk
+    0x8A, 0xF0, // 20C: VA = VF
+    0x22, 0x96, // 20E: print
+    0x61, 0x10, // 210: V1 = 0x10
+    0x62, 0x81, // 212: V2 = 0x81
+    0x81, 0x26, // 214: V1 >>= 1, shift quirk
+    0x8D, 0xF0, // 216: VD = VF
+    0x8A, 0x10, // 218: VA = V1
+    0x22, 0x96, // 21A: print
+    0x8A, 0xD0, // 21C: VA = VD
+    0x22, 0x96, // 21E: print
+    0x6B, 0x04, // 220: VB = 4
+    0x6C, 0x06, // 222: VC = 6
+    0x60, 0x01, // 224: V0 = 1
+    0x61, 0x02, // 226: V1 = 2
+    0xA4, 0x00, // 228: I = 400
+    0xF1, 0x55, // 22A: store V0 to V1, memory quirk
+    0x60, 0x07, // 22C: V0 = 7
+    0xF0, 0x55, // 22E: store V0 where I ended up
+    0xA4, 0x00, // 230: I = 400
+    0xF0, 0x65, // 232: load V0
+    0x8A, 0x00, // 234: VA = V0
+    0x22, 0x96, // 236: print
+    0x61, 0x05, // 238: V1 = 5
+    0x62, 0x09, // 23A: V2 = 9
+    0x81, 0x25, // 23C: V1 -= V2
+    0x8D, 0xF0, // 23E: VD = VF
+    0x8A, 0x10, // 240: VA = V1
+    0x22, 0x96, // 242: print
+    0x8A, 0xD0, // 244: VA = VD
+    0x22, 0x96, // 246: print
+    0x6B, 0x04, // 248: VB = 4
+    0x6C, 0x0C, // 24A: VC = 12
+    0x61, 0xFF, // 24C: V1 = 0xFF
+    0x62, 0x02, // 24E: V2 = 2
+    0x81, 0x24, // 250: V1 += V2
+    0x8D, 0xF0, // 252: VD = VF
+    0x8A, 0x10, // 254: VA = V1
+    0x22, 0x96, // 256: print
+    0x8A, 0xD0, // 258: VA = VD
+    0x22, 0x96, // 25A: print
+    0x6B, 0x04, // 25C: VB = 4
+    0x6C, 0x12, // 25E: VC = 18
+    0x61, 0x03, // 260: V1 = 3
+    0x62, 0xC0, // 262: V2 = 0xC0
+    0x81, 0x2E, // 264: V1 <<= 1, shift quirk
+    0x8D, 0xF0, // 266: VD = VF
+    0x8A, 0x10, // 268: VA = V1
+    0x22, 0x96, // 26A: print
+    0x8A, 0xD0, // 26C: VA = VD
+    0x22, 0x96, // 26E: print
+    0xC0, 0xFF, // 270: V0 = random
+    0x8A, 0x00, // 272: VA = V0
+    0x22, 0x96, // 274: print
+    0x6B, 0x04, // 276: VB = 4
+    0x6C, 0x18, // 278: VC = 24
+    0x61, 0x05, // 27A: V1 = 5
+    0x62, 0x09, // 27C: V2 = 9
+    0x81, 0x27, // 27E: V1 = V2 - V1
+    0x8D, 0xF0, // 280: VD = VF
+    0x8A, 0x10, // 282: VA = V1
+    0x22, 0x96, // 284: print
+    0x8A, 0xD0, // 286: VA = VD
+    0x22, 0x96, // 288: print
+    0x6B, 0x3E, // 28A: VB = 62
+    0x6C, 0x1C, // 28C: VC = 28
+    0x60, 0x08, // 28E: V0 = 8
+    0xF0, 0x29, // 290: I = font digit V0
+    0xDB, 0xC5, // 292: draw across the corner, clip quirk
+    0x12, 0x94, // 294: wait forever
+    0xA5, 0x00, // 296: print: I = 500
+    0xFA, 0x33, // 298: store the BCD of VA
+    0xF2, 0x65, // 29A: load the digits into V0 to V2
+    0xF0, 0x29, // 29C: I = font digit V0
+    0xDB, 0xC5, // 29E: draw it at VB, VC
+    0x7B, 0x05, // 2A0: VB += 5
+    0xF1, 0x29, // 2A2: I = font digit V1
+    0xDB, 0xC5, // 2A4: draw it
+    0x7B, 0x05, // 2A6: VB += 5
+    0xF2, 0x29, // 2A8: I = font digit V2
+    0xDB, 0xC5, // 2AA: draw it
+    0x7B, 0x07, // 2AC: VB += 7
+    0x00, 0xEE, // 2AE: return
+];
+
+/// A key press or release fed to the machine at the start of the given frame
+struct ScriptedInput {
+    frame: u64,
+    button: usize,
+    pressed: bool,
+}
+
+fn tests_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn ascii_screen(chip: &Chip8) -> String {
+    let display = chip.display();
+    (0..display.height())
+        .map(|y| {
+            let mut line: String = (0..display.width())
+                .map(|x| if display.pixel(x, y) { '#' } else { '.' })
+                .collect();
+            line.push('\n');
+            line
+        })
+        .collect()
+}
+
+fn run(
+    rom: &[u8],
+    platform: Platform,
+    menu_choice: Option<u8>,
+    inputs: &[ScriptedInput],
+) -> String {
+    let mut chip = Chip8::new(None);
+    chip.set_quirks(platform.quirks());
+    chip.seed_rng(0);
+    chip.load_rom_bytes(rom);
+    if let Some(choice) = menu_choice {
+        chip.write_memory(MENU_CHOICE_ADDRESS, &[choice]).unwrap();
+    }
+
+    let mut scheduler = Scheduler::new(chip8_core::scheduler::DEFAULT_INSTRUCTIONS_PER_SECOND);
+    for frame in 0..FRAMES {
+        for input in inputs.iter().filter(|input| input.frame == frame) {
+            chip.key_press(input.button, input.pressed);
+        }
+        scheduler.run_frame(&mut chip);
+    }
+
+    ascii_screen(&chip)
+}
+
+fn timendus_rom(rom_name: &str) -> Vec<u8> {
+    let rom_path = tests_dir().join("roms").join(format!("{}.ch8", rom_name));
+    fs::read(&rom_path).unwrap_or_else(|error| {
+        panic!(
+            "{}: {}, see tests/roms/README.md for how to download it",
+            rom_path.display(),
+            error
+        )
+    })
+}
+
+///
+/// Runs a test ROM on every platform and checks the screens against the golden images
+///
+fn check(
+    rom_name: &str,
+    rom: &[u8],
+    menu_choice: impl Fn(Platform) -> Option<u8>,
+    inputs: &[ScriptedInput],
+) {
+    let bless = env::var_os("BLESS").is_some();
+    let mut failures = Vec::new();
+
+    let mut chip = Chip8::new(None);
+    chip.load_rom_bytes(rom);
+    let rom_line = format!("rom {:016x}\n", chip.rom_hash());
+
+    for platform in Platform::ALL {
+        let screen = rom_line.clone() + &run(rom, platform, menu_choice(platform), inputs);
+        let golden_path = tests_dir()
+            .join("golden")
+            .join(format!("{}-{}.txt", rom_name, platform));
+
+        if bless {
+            fs::write(&golden_path, &screen).unwrap();
+            continue;
+        }
+
+        match fs::read_to_string(&golden_path) {
+            Ok(golden) if golden == screen => (),
+            Ok(golden) if !golden.starts_with(&rom_line) => failures.push(format!(
+                "{} was made from another version of {}, use the release pinned in tests/roms/README.md",
+                golden_path.display(),
+                rom_name
+            )),
+            Ok(golden) => failures.push(format!(
+                "{} on {} differs from {}\nexpected:\n{}actual:\n{}",
+                rom_name,
+                platform,
+                golden_path.display(),
+                golden,
+                screen
+            )),
+            Err(_) => failures.push(format!(
+                "{} is missing, run with BLESS=1 to create it from:\n{}",
+                golden_path.display(),
+                screen
+            )),
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn probe() {
+    check("probe", PROBE, |_| None, &[]);
+}
+
+#[test]
+#[ignore = "needs the Timendus ROMs, see tests/roms/README.md"]
+fn corax_plus() {
+    check("3-corax+", &timendus_rom("3-corax+"), |_| None, &[]);
+}
+
+#[test]
+#[ignore = "needs the Timendus ROMs, see tests/roms/README.md"]
+fn flags() {
+    check("4-flags", &timendus_rom("4-flags"), |_| None, &[]);
+}
+
+#[test]
+#[ignore = "needs the Timendus ROMs, see tests/roms/README.md"]
+fn quirks() {
+    // 1 selects the CHIP-8 checks, 3 the legacy SUPER-CHIP ones
+    check(
+        "5-quirks",
+        &timendus_rom("5-quirks"),
+        |platform| match platform {
+            Platform::Legacy | Platform::CosmacVip => Some(1),
+            Platform::Chip48 | Platform::SuperChip => Some(3),
+        },
+        &[],
+    );
+}
+
+#[test]
+#[ignore = "needs the Timendus ROMs, see tests/roms/README.md"]
+fn keypad() {
+    // The EX9E test lights up every button while it is held, press them one after another
+    let inputs: Vec<_> = (0..16)
+        .flat_map(|button| {
+            let frame = 60 + button as u64 * 20;
+            [
+                ScriptedInput {
+                    frame,
+                    button,
+                    pressed: true,
+                },
+                ScriptedInput {
+                    frame: frame + 10,
+                    button,
+                    pressed: false,
+                },
+            ]
+        })
+        .collect();
+
+    check("6-keypad", &timendus_rom("6-keypad"), |_| Some(1), &inputs);
+}